@@ -0,0 +1,30 @@
+use thiserror::Error;
+
+
+/// Everything that can fail a reconcile, grouped so `error_policy` can pick a retry delay.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Kubernetes API error: {0}")]
+    Kube(#[from] kube::Error),
+
+    #[error("ZooKeeper error: {0}")]
+    Zookeeper(#[from] zookeeper::ZkError),
+
+    #[error("ZooKeeper operation timed out after {0:?}")]
+    ZookeeperTimeout(std::time::Duration),
+
+    #[error("Cannot inspect ZooKeeper server {0}: {1}")]
+    Inspection(String, String),
+
+    #[error("Invalid ZookeeperCluster spec: {0}")]
+    InvalidSpec(String),
+
+    #[error("ZooKeeper credentials unavailable: {0}")]
+    Credentials(String),
+
+    #[error("Upgrade failed: {0}")]
+    UpgradeFailed(String),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
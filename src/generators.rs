@@ -0,0 +1,485 @@
+use std::collections::BTreeMap;
+use k8s_openapi::api::apps::v1 as appsv1;
+use k8s_openapi::api::core::v1 as v1;
+use k8s_openapi::api::policy::v1 as policyv1;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
+use kube::{Resource, ResourceExt};
+use super::zookeeper_type::{ZookeeperCluster, KUBERNETES_CLUSTER_DOMAIN};
+
+
+/// Annotation holding a hash of the generated spec, used to detect drift without
+/// comparing against fields the API server defaults.
+pub const SPEC_HASH_ANNOTATION: &str = "zookeeper.pravega.io/spec-hash";
+/// Pod template annotation holding a hash of the rendered configuration, so a config
+/// change rolls the servers.
+pub const CONFIG_HASH_ANNOTATION: &str = "zookeeper.pravega.io/config-hash";
+
+const ZK_CONTAINER_NAME: &str = "zookeeper";
+const ZK_DATA_VOLUME_NAME: &str = "data";
+const ZK_DATA_MOUNT_PATH: &str = "/data";
+const ZK_CONF_VOLUME_NAME: &str = "conf";
+const ZK_CONF_MOUNT_PATH: &str = "/conf";
+const ZK_START_SCRIPT: &str = "/usr/local/bin/zookeeperStart.sh";
+const ZK_READY_SCRIPT: &str = "zookeeperReady.sh";
+const ZK_LIVE_SCRIPT: &str = "zookeeperLive.sh";
+const ZK_TEARDOWN_SCRIPT: &str = "zookeeperTeardown.sh";
+
+
+/// Labels selecting every pod that belongs to the cluster.
+pub fn selector_labels(z: &ZookeeperCluster) -> BTreeMap<String, String> {
+    BTreeMap::from([("app".to_owned(), z.name_any())])
+}
+
+/// Hash of a serializable spec, the same for identical input across operator builds so
+/// an upgraded operator does not see drift, or roll pods, where nothing changed.
+pub fn spec_hash<T: serde::Serialize>(spec: &T) -> String {
+    format!("{:x}", fnv1a(serde_json::to_string(spec).unwrap_or_default().as_bytes()))
+}
+
+/// 64-bit FNV-1a, chosen over std's DefaultHasher whose algorithm may change between
+/// Rust releases.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Returns the spec hash annotation recorded on an object, if any.
+pub fn recorded_spec_hash(meta: &metav1::ObjectMeta) -> Option<&String> {
+    meta.annotations.as_ref().and_then(|a| a.get(SPEC_HASH_ANNOTATION))
+}
+
+fn object_meta(z: &ZookeeperCluster, name: String, labels: BTreeMap<String, String>) -> metav1::ObjectMeta {
+    metav1::ObjectMeta {
+        name: Some(name),
+        namespace: z.namespace(),
+        labels: Some(labels),
+        owner_references: z.controller_owner_ref(&()).map(|o| vec![o]),
+        ..Default::default()
+    }
+}
+
+/// Build the StatefulSet running the zookeeper ensemble with `replicas` members, which
+/// trails `spec.replicas` while the ensemble is being resized. `super_digest` is the
+/// one `make_config_map` hands the servers.
+pub fn make_statefulset(z: &ZookeeperCluster, replicas: i32, super_digest: Option<&str>) -> appsv1::StatefulSet {
+    let spec = &z.spec;
+    let pod = spec.pod.clone().unwrap_or_default();
+
+    let mut pod_labels = pod.labels.clone().unwrap_or_default();
+    pod_labels.extend(selector_labels(z));
+    pod_labels.insert("kind".to_owned(), "ZookeeperMember".to_owned());
+
+    // env.sh carries CLUSTER_SIZE, which must not restart the ensemble on scaling, but
+    // also the superDigest, which servers only pick up on a restart
+    let mut config = make_config_map(z, super_digest).data.unwrap_or_default();
+    config.remove("env.sh");
+    if let Some(super_digest) = super_digest {
+        config.insert("superDigest".to_owned(), super_digest.to_owned());
+    }
+    let mut pod_annotations = pod.annotations.clone().unwrap_or_default();
+    pod_annotations.insert(CONFIG_HASH_ANNOTATION.to_owned(), spec_hash(&config));
+
+    let mut volume_claim_templates = None;
+    let mut volumes = vec![v1::Volume {
+        name: ZK_CONF_VOLUME_NAME.to_owned(),
+        config_map: Some(v1::ConfigMapVolumeSource {
+            name: Some(z.get_config_map_name()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }];
+    if spec.storagetype.as_deref() == Some("ephemeral") {
+        volumes.push(v1::Volume {
+            name: ZK_DATA_VOLUME_NAME.to_owned(),
+            empty_dir: Some(
+                spec.ephemeral
+                    .as_ref()
+                    .and_then(|e| e.emptydirvolumesource.clone())
+                    .unwrap_or_default(),
+            ),
+            ..Default::default()
+        });
+    } else if let Some(persistence) = spec.persistence.as_ref() {
+        volume_claim_templates = Some(vec![v1::PersistentVolumeClaim {
+            metadata: metav1::ObjectMeta {
+                name: Some(ZK_DATA_VOLUME_NAME.to_owned()),
+                labels: Some(selector_labels(z)),
+                annotations: persistence.annotations.clone(),
+                ..Default::default()
+            },
+            spec: persistence.persistent_volume_claim_spec.clone(),
+            ..Default::default()
+        }]);
+    }
+
+    let probes = spec.probes.as_ref();
+    let container = v1::Container {
+        name: ZK_CONTAINER_NAME.to_owned(),
+        image: spec.image.as_ref().map(|i| i.to_string()),
+        image_pull_policy: spec.image.as_ref().and_then(|i| i.pull_policy.clone()),
+        ports: spec.ports.clone(),
+        command: Some(vec![ZK_START_SCRIPT.to_owned()]),
+        readiness_probe: probes
+            .and_then(|p| p.readiness_probe.as_ref())
+            .map(|p| p.generate_probe(ZK_READY_SCRIPT)),
+        liveness_probe: probes
+            .and_then(|p| p.liveness_probe.as_ref())
+            .map(|p| p.generate_probe(ZK_LIVE_SCRIPT)),
+        volume_mounts: Some(vec![
+            v1::VolumeMount {
+                name: ZK_DATA_VOLUME_NAME.to_owned(),
+                mount_path: ZK_DATA_MOUNT_PATH.to_owned(),
+                ..Default::default()
+            },
+            v1::VolumeMount {
+                name: ZK_CONF_VOLUME_NAME.to_owned(),
+                mount_path: ZK_CONF_MOUNT_PATH.to_owned(),
+                ..Default::default()
+            },
+        ]),
+        lifecycle: Some(v1::Lifecycle {
+            pre_stop: Some(v1::LifecycleHandler {
+                exec: Some(v1::ExecAction {
+                    command: Some(vec![ZK_TEARDOWN_SCRIPT.to_owned()]),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let sts_spec = appsv1::StatefulSetSpec {
+        service_name: z.get_headless_service_name(),
+        replicas: Some(replicas),
+        selector: metav1::LabelSelector {
+            match_labels: Some(selector_labels(z)),
+            ..Default::default()
+        },
+        // the operator replaces pods itself so it can leave the leader for last
+        update_strategy: Some(appsv1::StatefulSetUpdateStrategy {
+            type_: Some("OnDelete".to_owned()),
+            ..Default::default()
+        }),
+        pod_management_policy: Some("OrderedReady".to_owned()),
+        template: v1::PodTemplateSpec {
+            metadata: Some(metav1::ObjectMeta {
+                generate_name: Some(z.name_any()),
+                labels: Some(pod_labels),
+                annotations: Some(pod_annotations),
+                ..Default::default()
+            }),
+            spec: Some(v1::PodSpec {
+                containers: vec![container],
+                volumes: Some(volumes),
+                affinity: pod.affinity.clone(),
+                node_selector: pod.node_selector.clone(),
+                termination_grace_period_seconds: pod.termination_grace_period_seconds,
+                service_account_name: pod.service_account_name.clone(),
+                ..Default::default()
+            }),
+        },
+        volume_claim_templates,
+        ..Default::default()
+    };
+
+    let mut metadata = object_meta(z, z.name_any(), selector_labels(z));
+    metadata.annotations = Some(BTreeMap::from([(
+        SPEC_HASH_ANNOTATION.to_owned(),
+        spec_hash(&sts_spec),
+    )]));
+    appsv1::StatefulSet {
+        metadata,
+        spec: Some(sts_spec),
+        ..Default::default()
+    }
+}
+
+/// Build the ConfigMap mounted at /conf in every zookeeper server. `super_digest`, from
+/// the credentials in `spec.auth`, makes the operator a superuser of the servers.
+pub fn make_config_map(z: &ZookeeperCluster, super_digest: Option<&str>) -> v1::ConfigMap {
+    let data = BTreeMap::from([
+        ("zoo.cfg".to_owned(), make_zk_config_string(z)),
+        ("log4j.properties".to_owned(), make_zk_log4j_config_string()),
+        ("log4j-quiet.properties".to_owned(), make_zk_log4j_quiet_config_string()),
+        ("logback.xml".to_owned(), make_zk_logback_config_string()),
+        ("env.sh".to_owned(), make_zk_env_config_string(z, super_digest)),
+    ]);
+    let mut metadata = object_meta(z, z.get_config_map_name(), selector_labels(z));
+    metadata.annotations = Some(BTreeMap::from([(
+        SPEC_HASH_ANNOTATION.to_owned(),
+        spec_hash(&data),
+    )]));
+    v1::ConfigMap {
+        metadata,
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+/// Render zoo.cfg from `spec.config`. Unset optional settings are left to the
+/// zookeeper defaults; additional config is appended in key order.
+pub fn make_zk_config_string(z: &ZookeeperCluster) -> String {
+    let config = z.spec.zkconfig.clone().unwrap_or_default();
+    let mut lines = vec![
+        "4lw.commands.whitelist=cons, envi, conf, crst, srvr, stat, mntr, ruok".to_owned(),
+        format!("dataDir={}", ZK_DATA_MOUNT_PATH),
+        "standaloneEnabled=false".to_owned(),
+        "reconfigEnabled=true".to_owned(),
+        // ACLs only protect operator znodes once the operator itself authenticates
+        format!("skipACL={}", if z.spec.auth.is_some() { "no" } else { "yes" }),
+        "metricsProvider.className=org.apache.zookeeper.metrics.prometheus.PrometheusMetricsProvider".to_owned(),
+        format!("metricsProvider.httpPort={}", z.get_port("metrics", 7000)),
+        "metricsProvider.exportJvmInfo=true".to_owned(),
+    ];
+    let settings = [
+        ("initLimit", config.init_limit),
+        ("syncLimit", config.sync_limit),
+        ("tickTime", config.tick_time),
+        ("globalOutstandingLimit", config.global_outstanding_limit),
+        ("preAllocSize", config.pre_alloc_size),
+        ("snapCount", config.snap_count),
+        ("commitLogCount", config.commit_log_count),
+        ("snapSizeLimitInKb", config.snap_size_limit_in_kb),
+        ("maxCnxns", config.max_cnxns),
+        ("maxClientCnxns", config.max_client_cnxns),
+        ("minSessionTimeout", config.min_session_timeout),
+        ("maxSessionTimeout", config.max_session_timeout),
+        ("autopurge.snapRetainCount", config.auto_purge_snap_retain_count),
+        ("autopurge.purgeInterval", config.auto_purge_purge_interval),
+    ];
+    for (key, value) in settings {
+        if let Some(value) = value {
+            lines.push(format!("{}={}", key, value));
+        }
+    }
+    lines.push(format!(
+        "quorumListenOnAllIPs={}",
+        config.quorum_listen_on_all_ips.unwrap_or(false)
+    ));
+    lines.push(format!("admin.serverPort={}", z.get_port("admin-server", 8080)));
+    lines.push(format!("dynamicConfigFile={}/zoo.cfg.dynamic", ZK_DATA_MOUNT_PATH));
+
+    let additional: BTreeMap<_, _> = config.additional_config.unwrap_or_default().into_iter().collect();
+    for (key, value) in additional {
+        lines.push(format!("{}={}", key, value));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Render env.sh, sourced by the start/ready/teardown scripts in the zookeeper image.
+pub fn make_zk_env_config_string(z: &ZookeeperCluster, super_digest: Option<&str>) -> String {
+    let namespace = z.namespace().unwrap_or_default();
+    let mut env = format!(
+        "#!/usr/bin/env bash\n\n\
+         DOMAIN={}.{}.svc.{}\n\
+         QUORUM_PORT={}\n\
+         LEADER_PORT={}\n\
+         CLIENT_HOST={}\n\
+         CLIENT_PORT={}\n\
+         ADMIN_SERVER_HOST={}\n\
+         ADMIN_SERVER_PORT={}\n\
+         CLUSTER_NAME={}\n\
+         CLUSTER_SIZE={}\n",
+        z.get_headless_service_name(),
+        namespace,
+        KUBERNETES_CLUSTER_DOMAIN,
+        z.get_port("quorum", 2888),
+        z.get_port("leader-election", 3888),
+        z.get_client_service_name(),
+        z.get_port("client", 2181),
+        z.get_admin_server_service_name(),
+        z.get_port("admin-server", 8080),
+        z.name_any(),
+        z.spec.replicas,
+    );
+    if let Some(super_digest) = super_digest {
+        // zkServer.sh adds SERVER_JVMFLAGS to the server's JVM only
+        env.push_str(&format!(
+            "export SERVER_JVMFLAGS=\"$SERVER_JVMFLAGS -Dzookeeper.DigestAuthenticationProvider.superDigest={}\"\n",
+            super_digest,
+        ));
+    }
+    env
+}
+
+/// Member ordinal of a data volume claim the StatefulSet created, or None for any other
+/// claim.
+pub fn data_volume_claim_ordinal(z: &ZookeeperCluster, claim_name: &str) -> Option<i32> {
+    claim_name.strip_prefix(&format!("{}-{}-", ZK_DATA_VOLUME_NAME, z.name_any()))?.parse().ok()
+}
+
+/// Dynamic config line that makes the member with `ordinal` a voter; its server id is
+/// the ordinal plus one, as assigned by the pod start script.
+pub fn make_server_spec(z: &ZookeeperCluster, ordinal: i32) -> String {
+    format!(
+        "server.{}={}:{}:{}:participant;0.0.0.0:{}",
+        ordinal + 1,
+        z.get_member_address(ordinal),
+        z.get_port("quorum", 2888),
+        z.get_port("leader-election", 3888),
+        z.get_port("client", 2181),
+    )
+}
+
+fn make_zk_log4j_config_string() -> String {
+    "zookeeper.root.logger=CONSOLE\n\
+     zookeeper.console.threshold=INFO\n\
+     log4j.rootLogger=${zookeeper.root.logger}\n\
+     log4j.appender.CONSOLE=org.apache.log4j.ConsoleAppender\n\
+     log4j.appender.CONSOLE.Threshold=${zookeeper.console.threshold}\n\
+     log4j.appender.CONSOLE.layout=org.apache.log4j.PatternLayout\n\
+     log4j.appender.CONSOLE.layout.ConversionPattern=%d{ISO8601} [myid:%X{myid}] - %-5p [%t:%C{1}@%L] - %m%n\n"
+        .to_owned()
+}
+
+fn make_zk_log4j_quiet_config_string() -> String {
+    "log4j.rootLogger=ERROR, CONSOLE\n\
+     log4j.appender.CONSOLE=org.apache.log4j.ConsoleAppender\n\
+     log4j.appender.CONSOLE.Threshold=ERROR\n\
+     log4j.appender.CONSOLE.layout=org.apache.log4j.PatternLayout\n\
+     log4j.appender.CONSOLE.layout.ConversionPattern=%d{ISO8601} [myid:%X{myid}] - %-5p [%t:%C{1}@%L] - %m%n\n"
+        .to_owned()
+}
+
+fn make_zk_logback_config_string() -> String {
+    "<configuration>\n\
+     \x20 <property name=\"zookeeper.console.threshold\" value=\"INFO\" />\n\
+     \x20 <appender name=\"CONSOLE\" class=\"ch.qos.logback.core.ConsoleAppender\">\n\
+     \x20   <encoder>\n\
+     \x20     <pattern>%d{ISO8601} [myid:%X{myid}] - %-5p [%t:%C{1}@%L] - %m%n</pattern>\n\
+     \x20   </encoder>\n\
+     \x20   <filter class=\"ch.qos.logback.classic.filter.ThresholdFilter\">\n\
+     \x20     <level>${zookeeper.console.threshold}</level>\n\
+     \x20   </filter>\n\
+     \x20 </appender>\n\
+     \x20 <root level=\"INFO\">\n\
+     \x20   <appender-ref ref=\"CONSOLE\" />\n\
+     \x20 </root>\n\
+     </configuration>\n"
+        .to_owned()
+}
+
+/// Build the ClusterIP Service clients connect through.
+pub fn make_client_service(z: &ZookeeperCluster) -> v1::Service {
+    let ports = vec![service_port(z, "tcp-client", "client", 2181)];
+    make_service(z, z.get_client_service_name(), ports, true)
+}
+
+/// Build the LoadBalancer or NodePort Service exposing clients outside the cluster,
+/// if `spec.externalClientService` asks for one.
+pub fn make_external_client_service(z: &ZookeeperCluster) -> Option<v1::Service> {
+    let external = z.spec.external_client_service.as_ref()?;
+    let mut port = service_port(z, "tcp-client", "client", 2181);
+    port.node_port = external.node_port;
+    let mut svc = make_service(z, z.get_external_client_service_name(), vec![port], true);
+    let svc_spec = svc.spec.as_mut().unwrap();
+    svc_spec.type_ = external.service_type.clone();
+    svc_spec.load_balancer_source_ranges = external.load_balancer_source_ranges.clone();
+
+    let mut annotations = external.annotations.clone().unwrap_or_default();
+    annotations.insert(SPEC_HASH_ANNOTATION.to_owned(), spec_hash(&(svc_spec, &external.annotations)));
+    svc.metadata.annotations = Some(annotations);
+    Some(svc)
+}
+
+/// Build the headless Service giving every server a stable DNS name for quorum traffic.
+pub fn make_headless_service(z: &ZookeeperCluster) -> v1::Service {
+    let ports = vec![
+        service_port(z, "tcp-client", "client", 2181),
+        service_port(z, "tcp-quorum", "quorum", 2888),
+        service_port(z, "tcp-leader-election", "leader-election", 3888),
+        service_port(z, "tcp-metrics", "metrics", 7000),
+        service_port(z, "tcp-admin-server", "admin-server", 8080),
+    ];
+    make_service(z, z.get_headless_service_name(), ports, false)
+}
+
+/// Build the ClusterIP Service exposing the zookeeper AdminServer.
+pub fn make_admin_server_service(z: &ZookeeperCluster) -> v1::Service {
+    let ports = vec![service_port(z, "tcp-admin-server", "admin-server", 8080)];
+    make_service(z, z.get_admin_server_service_name(), ports, true)
+}
+
+fn service_port(z: &ZookeeperCluster, name: &str, container_port_name: &str, default: i32) -> v1::ServicePort {
+    v1::ServicePort {
+        name: Some(name.to_owned()),
+        port: z.get_port(container_port_name, default),
+        ..Default::default()
+    }
+}
+
+fn make_service(z: &ZookeeperCluster, name: String, ports: Vec<v1::ServicePort>, cluster_ip: bool) -> v1::Service {
+    let mut labels = selector_labels(z);
+    labels.insert("headless".to_owned(), (!cluster_ip).to_string());
+    let svc_spec = v1::ServiceSpec {
+        ports: Some(ports),
+        selector: Some(selector_labels(z)),
+        cluster_ip: if cluster_ip { None } else { Some("None".to_owned()) },
+        // peers must resolve each other before they are ready to form a quorum
+        publish_not_ready_addresses: if cluster_ip { None } else { Some(true) },
+        ..Default::default()
+    };
+    let mut metadata = object_meta(z, name, labels);
+    metadata.annotations = Some(BTreeMap::from([(
+        SPEC_HASH_ANNOTATION.to_owned(),
+        spec_hash(&svc_spec),
+    )]));
+    v1::Service {
+        metadata,
+        spec: Some(svc_spec),
+        ..Default::default()
+    }
+}
+
+/// Build the PodDisruptionBudget keeping a quorum of servers through voluntary disruptions.
+pub fn make_pod_disruption_budget(z: &ZookeeperCluster) -> policyv1::PodDisruptionBudget {
+    let pdb_spec = policyv1::PodDisruptionBudgetSpec {
+        max_unavailable: Some(IntOrString::Int(z.get_max_unavailable_replicas())),
+        selector: Some(metav1::LabelSelector {
+            match_labels: Some(selector_labels(z)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let mut metadata = object_meta(z, z.name_any(), selector_labels(z));
+    metadata.annotations = Some(BTreeMap::from([(
+        SPEC_HASH_ANNOTATION.to_owned(),
+        spec_hash(&pdb_spec),
+    )]));
+    policyv1::PodDisruptionBudget {
+        metadata,
+        spec: Some(pdb_spec),
+        ..Default::default()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_hash_is_fixed_across_builds() {
+        // published FNV-1a test vectors
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(spec_hash(&"a"), format!("{:x}", fnv1a(b"\"a\"")));
+    }
+
+    #[test]
+    fn super_digest_reaches_the_servers() {
+        let z = ZookeeperCluster::new("zk", Default::default());
+        let config_hash = |super_digest| {
+            make_statefulset(&z, 3, super_digest).spec.unwrap().template.metadata.unwrap().annotations.unwrap()
+                [CONFIG_HASH_ANNOTATION].clone()
+        };
+
+        let env = make_zk_env_config_string(&z, Some("super:xQJmxLMiHGwaqBvst5y6rkB6HQs="));
+        assert!(env.ends_with(
+            "export SERVER_JVMFLAGS=\"$SERVER_JVMFLAGS -Dzookeeper.DigestAuthenticationProvider.superDigest=super:xQJmxLMiHGwaqBvst5y6rkB6HQs=\"\n"
+        ));
+        assert!(!make_zk_env_config_string(&z, None).contains("SERVER_JVMFLAGS"));
+        assert_ne!(config_hash(None), config_hash(Some("super:xQJmxLMiHGwaqBvst5y6rkB6HQs=")));
+    }
+}
@@ -1,67 +1,882 @@
 use kube::{
     api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams, ResourceExt},
     core::crd::CustomResourceExt,
-    Client, CustomResource,
-    runtime::controller::{Controller, Action}
+    Client, Resource,
+    runtime::controller::{self, applier, trigger_owners, trigger_self, Action, ReconcileRequest},
+    runtime::reflector::{self, reflector, ObjectRef},
+    runtime::utils::{CancelableJoinHandle, StreamBackoff},
+    runtime::watcher::{self, watcher},
+    runtime::WatchStreamExt,
+    runtime::finalizer::{self, finalizer, Event as FinalizerEvent},
 };
-use serde::{Deserialize, Serialize};
 use tokio::time::Duration;
+use tokio::runtime::Handle;
 use tokio::time::sleep;
-use futures::StreamExt;
+use futures::{stream, StreamExt};
 use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
-use k8s_openapi::api::core::v1::Pod;
-use schemars::JsonSchema;
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::{ConfigMap, PersistentVolumeClaim, Pod, Secret, Service};
+use k8s_openapi::api::policy::v1::PodDisruptionBudget;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
 use std::sync::Arc;
-use thiserror::Error;
 use tracing::*;
+mod error;
 mod zookeeper_type;
 mod status;
 mod zookeeper_client_go;
-use zookeeper_type::{ZookeeperClusterSpec, ZookeeperCluster};
-use zookeeper_client_go as zk;
+#[cfg(test)]
+mod zookeeper_client_fake;
+mod zookeeper_client_pool;
+mod zookeeper_reconfig;
+mod zookeeper_health;
+mod zookeeper_version;
+mod generators;
+use zookeeper_type::{get_rolling_restart_annotation, ZookeeperCluster, AUTH_SECRET_PASSWORD_KEY, AUTH_SECRET_USERNAME_KEY};
+use status::{UPGRADE_FAILED_REASON, UPGRADE_IN_PROGRESS_REASON, UPGRADE_NOT_SUPPORTED_REASON, ClusterConditionType, MembersStatus, ScalingStatus, ServerStatus, ZookeeperClusterStatus, SCALING_PHASE_REMOVING_MEMBER, SCALING_PHASE_WAITING_FOR_POD, SCALING_PHASE_WAITING_FOR_POD_DELETION, SCALING_PHASE_WAITING_FOR_SYNC, CONDITION_FALSE, CONDITION_TRUE, CONDITION_UNKNOWN};
+use zookeeper_client_go::{DigestCredentials, ZookeeperClient, ZookeeperEvent};
+use zookeeper_client_pool::ZookeeperClientPool;
+use zookeeper_health::ServerStats;
+use error::Error;
 
 
-#[derive(Debug, Error)]
-enum Error {}
 const RECONCILE_TIME: Duration = Duration::from_secs(30);
+/// Requeue interval of a cluster whose members are all up.
+const RESYNC_TIME: Duration = Duration::from_secs(300);
+/// How old the server stats in status may get before a reconcile refreshes them. A little
+/// under RESYNC_TIME so every periodic requeue picks them up, while the reconcile their
+/// own status write triggers finds them fresh and leaves them be.
+const SERVER_STATS_INTERVAL: Duration = Duration::from_secs(240);
+
+/// Pod label the StatefulSet controller sets to the revision a pod was created from.
+const REVISION_LABEL: &str = "controller-revision-hash";
+
+/// Field manager used for every server-side apply issued by the operator.
+const FIELD_MANAGER: &str = "zookeeper-operator";
+/// Field manager owning the spec fields filled in by `with_defaults`.
+const DEFAULTS_FIELD_MANAGER: &str = "zookeeper-operator-defaults";
 
 struct ZookeeperClusterReconciler {
     client: kube::Client,
-    log: tracing_subscriber::fmt::Subscriber,
     // scheme: kube::runtime::Scheme, can not find same in rust
-    zk_client: zk::DefaultZookeeperClient,
+    zk_clients: Arc<ZookeeperClientPool>,
+}
+
+
+/// Finalizer held on every cluster so its external state is cleaned up before it goes.
+const ZK_FINALIZER: &str = "zookeeper.pravega.io/cleanup";
+
+async fn reconcile(g: Arc<ZookeeperCluster>, ctx: Arc<ZookeeperClusterReconciler>) -> Result<Action, Error> {
+    let zk_api: Api<ZookeeperCluster> = Api::namespaced(ctx.client.clone(), &g.namespace().unwrap_or_default());
+    finalizer(&zk_api, ZK_FINALIZER, g, |event| async {
+        match event {
+            FinalizerEvent::Apply(z) => apply_cluster(z, ctx.clone()).await,
+            FinalizerEvent::Cleanup(z) => cleanup_cluster(z, ctx.clone()).await,
+        }
+    })
+    .await
+    .map_err(|e| match e {
+        finalizer::Error::ApplyFailed(e) | finalizer::Error::CleanupFailed(e) => e,
+        finalizer::Error::AddFinalizer(e) | finalizer::Error::RemoveFinalizer(e) => Error::Kube(e),
+        finalizer::Error::UnnamedObject => Error::InvalidSpec("metadata.name is missing".to_owned()),
+    })
 }
 
+/// Release what a deleted cluster leaves behind outside its owned objects, which
+/// Kubernetes garbage collects on its own: the metadata znode, reclaimable volumes and
+/// the pooled zookeeper connection.
+async fn cleanup_cluster(z: Arc<ZookeeperCluster>, ctx: Arc<ZookeeperClusterReconciler>) -> Result<Action, Error> {
+    info!("Cleaning up zookeeper cluster {}/{}", z.namespace().unwrap_or_default(), z.name_any());
+    if z.status.as_ref().and_then(|s| s.meta_root_created) == Some(true) {
+        // the ensemble is going away too, so failing to reach it must not hold up the deletion
+        if let Err(e) = remove_cluster_metadata(&z, &ctx).await {
+            warn!("Leaving zookeeper metadata of {} behind: {}", z.name_any(), e);
+        }
+    }
+    if z.reclaims_volumes() {
+        let claims = list_data_volume_claims(&z, ctx.client.clone()).await?;
+        let claims = claims.into_iter().map(|(_, pvc)| pvc).collect();
+        delete_volume_claims(&z, ctx.client.clone(), claims).await?;
+    }
+    ctx.zk_clients.remove(&z.namespace().unwrap_or_default(), &z.name_any()).await;
+    Ok(Action::await_change())
+}
+
+/// Delete the cluster metadata znode, and its parent once no other cluster uses it.
+async fn remove_cluster_metadata(z: &ZookeeperCluster, ctx: &ZookeeperClusterReconciler) -> Result<(), Error> {
+    let credentials = read_credentials(z, ctx.client.clone()).await?;
+    let zk_client = ctx.zk_clients.get(z, credentials).await?;
+    let path = get_meta_path(z);
+    info!("Deleting zookeeper metadata znode {}", path);
+    match zk_client.delete_node(&path, None).await {
+        Ok(()) | Err(Error::Zookeeper(zookeeper::ZkError::NoNode)) => {}
+        Err(e) => return Err(e),
+    }
+    let parent = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or_default();
+    match zk_client.delete_node(parent, None).await {
+        Ok(()) | Err(Error::Zookeeper(zookeeper::ZkError::NoNode | zookeeper::ZkError::NotEmpty)) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
 
-async fn reconcile(mut g: Arc<ZookeeperCluster>, _ctx: Arc<ZookeeperClusterReconciler>) -> Result<Action, Error> {
-    // .. use api here to reconcile a child ConfigMap with ownerreferences
-    // see configmapgen_controller example for full info
-    let zk_client = &_ctx.zk_client;
+async fn apply_cluster(g: Arc<ZookeeperCluster>, _ctx: Arc<ZookeeperClusterReconciler>) -> Result<Action, Error> {
     let client = _ctx.client.clone();
     println!("reconciling {:?}", g);
-    let instance = Arc::get_mut(&mut g).unwrap();
-    let changed = instance.with_defaults();
-    if g.get_trigger_rolling_restart() {
+    let zk_api: Api<ZookeeperCluster> = Api::namespaced(client.clone(), &g.namespace().unwrap_or_default());
+    // The cached object is shared with the controller runtime, so default a copy and
+    // persist it; the rest of the reconcile continues from what the API server stored.
+    let mut instance = (*g).clone();
+    if instance.with_defaults() {
+        info!("Persisting defaults for zookeeper cluster {}/{}", instance.namespace().unwrap_or_default(), instance.name_any());
+        // the resourceVersion makes this a conditional write: should the user edit the spec
+        // after the cache read, the patch fails with a conflict instead of reverting the
+        // edit, and error_policy retries from the newer object shortly after
+        let patch = serde_json::json!({
+            "metadata": { "resourceVersion": instance.resource_version() },
+            "spec": instance.spec,
+        });
+        let pp = PatchParams { field_manager: Some(DEFAULTS_FIELD_MANAGER.to_owned()), ..Default::default() };
+        instance = zk_api.patch(&instance.name_any(), &pp, &Patch::Merge(&patch)).await?;
+    }
+    if instance.get_trigger_rolling_restart() {
         info!("Restarting zookeeper cluster");
-        // let (annotation_key, annotation_value) = get_rolling_restart_annotation();
-        // if instance.spec.pod.annotations.is_none() {
-        //     instance.spec.pod.annotations = Some(HashMap::new());
-        // }
-        // instance.spec.pod.annotations.as_mut().unwrap().insert(annotation_key, annotation_value);
-        // instance.set_trigger_rolling_restart(false);
-        // changed = true;
+        let (annotation_key, annotation_value) = get_rolling_restart_annotation();
+        // The new annotation changes the pod template, so reconcile_rollout restarts the
+        // servers one at a time; clearing the flag keeps this a one-shot restart.
+        let patch = serde_json::json!({
+            "spec": {
+                "triggerRollingRestart": false,
+                "pod": { "annotations": { annotation_key: annotation_value } },
+            }
+        });
+        let pp = PatchParams { field_manager: Some(FIELD_MANAGER.to_owned()), ..Default::default() };
+        instance = zk_api.patch(&instance.name_any(), &pp, &Patch::Merge(&patch)).await?;
+    }
+
+    let mut status = instance.status.clone().unwrap_or_default();
+    let result = reconcile_resources(&instance, &_ctx, &mut status).await;
+    match &result {
+        Ok(()) => status.set_condition(ClusterConditionType::Error, CONDITION_FALSE, "", ""),
+        Err(e) => status.set_condition(ClusterConditionType::Error, CONDITION_TRUE, "ReconcileFailed", &e.to_string()),
+    }
+    let pods_ready = status.is_true(ClusterConditionType::PodsReady);
+    reconcile_cluster_status(&instance, client.clone(), status).await?;
+    result?;
+
+    if !pods_ready {
+        // keep an eye on the ensemble until every member is up
+        return Ok(Action::requeue(RECONCILE_TIME));
+    }
+    Ok(Action::requeue(RESYNC_TIME))
+}
+
+/// Bring every owned object in line with the spec, recording what was observed in `status`.
+async fn reconcile_resources(z: &ZookeeperCluster, ctx: &ZookeeperClusterReconciler, status: &mut ZookeeperClusterStatus) -> Result<(), Error> {
+    let client = ctx.client.clone();
+    z.spec.validate().map_err(Error::InvalidSpec)?;
+    let credentials = read_credentials(z, client.clone()).await?;
+    let super_digest = credentials.as_ref().map(DigestCredentials::super_digest);
+    reconcile_config_map(z, client.clone(), super_digest.as_deref()).await?;
+    // the pod scripts read the cluster size on shutdown, so it must change before the
+    // StatefulSet does; an ensemble that cannot take the write holds the replica count
+    // where it is but still gets every other object, whose fix may be what it needs
+    let mut metadata_result = Ok(());
+    let replicas = if status.meta_root_created == Some(true) || status.is_true(ClusterConditionType::PodsReady) {
+        match connect_cluster_metadata(z, ctx, credentials, status).await {
+            Ok(zk_client) => reconcile_scale(z, client.clone(), Some(zk_client.as_ref()), status).await?,
+            Err(e) => {
+                warn!("Holding the size of zookeeper cluster {} until its metadata is updated: {}", z.name_any(), e);
+                metadata_result = Err(e);
+                statefulset_replicas(z, client.clone()).await?.unwrap_or(z.spec.replicas)
+            }
+        }
+    } else {
+        reconcile_scale(z, client.clone(), None, status).await?
+    };
+    // the pods run the version being rolled out, which is not the spec's when the upgrade
+    // to it was refused or rolled back
+    let mut running = z.clone();
+    let version = plan_upgrade(z, status);
+    if let Some(image) = running.spec.image.as_mut() {
+        image.tag = Some(version);
+    }
+    let sts = reconcile_statefulset(&running, client.clone(), replicas, super_digest.as_deref()).await?;
+    reconcile_volumes(z, client.clone(), replicas, status).await?;
+    reconcile_services(z, client.clone(), status).await?;
+    reconcile_pod_disruption_budget(z, client.clone()).await?;
+    observe_members(z, client.clone(), &sts, status).await?;
+    update_conditions(z, &sts, status);
+    reconcile_rollout(z, client.clone(), &sts, status).await?;
+    metadata_result
+}
+
+async fn reconcile_config_map(z: &ZookeeperCluster, client: Client, super_digest: Option<&str>) -> Result<(), Error> {
+    let cm_api: Api<ConfigMap> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    create_or_apply(&cm_api, generators::make_config_map(z, super_digest)).await?;
+    Ok(())
+}
+
+/// Digest credentials from the Secret named in `spec.auth`, or None when auth is off.
+async fn read_credentials(z: &ZookeeperCluster, client: Client) -> Result<Option<DigestCredentials>, Error> {
+    let auth = match &z.spec.auth {
+        Some(auth) => auth,
+        None => return Ok(None),
+    };
+    let secrets: Api<Secret> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    let secret = secrets.get_opt(&auth.secret_name).await?;
+    credentials_from_secret(&auth.secret_name, secret).map(Some)
+}
+
+/// Digest credentials held in the `username` and `password` keys of `secret`.
+fn credentials_from_secret(secret_name: &str, secret: Option<Secret>) -> Result<DigestCredentials, Error> {
+    let secret = secret.ok_or_else(|| Error::Credentials(format!("secret {} not found", secret_name)))?;
+    let data = secret.data.unwrap_or_default();
+    let read_key = |key: &str| {
+        let value = data.get(key)
+            .ok_or_else(|| Error::Credentials(format!("secret {} has no {:?} key", secret_name, key)))?;
+        String::from_utf8(value.0.clone())
+            .map_err(|_| Error::Credentials(format!("secret {} key {:?} is not valid UTF-8", secret_name, key)))
+    };
+    Ok(DigestCredentials {
+        username: read_key(AUTH_SECRET_USERNAME_KEY)?,
+        password: read_key(AUTH_SECRET_PASSWORD_KEY)?,
+    })
+}
+
+/// Znode holding the cluster metadata the pod scripts rely on.
+fn get_meta_path(z: &ZookeeperCluster) -> String {
+    format!("/zookeeper-operator/{}", z.name_any())
+}
+
+/// Connect to the ensemble and bring its metadata znode in line with the spec.
+async fn connect_cluster_metadata(z: &ZookeeperCluster, ctx: &ZookeeperClusterReconciler, credentials: Option<DigestCredentials>, status: &mut ZookeeperClusterStatus) -> Result<Arc<dyn ZookeeperClient>, Error> {
+    let zk_client = ctx.zk_clients.get(z, credentials).await?;
+    reconcile_cluster_metadata(z, zk_client.as_ref(), status).await?;
+    Ok(zk_client)
+}
+
+/// Create the cluster metadata znode the first time round, then keep its CLUSTER_SIZE in
+/// step with `spec.replicas`. Updates carry the version read, so a concurrent writer
+/// makes this fail with BadVersion and retry instead of being overwritten.
+async fn reconcile_cluster_metadata(z: &ZookeeperCluster, zk_client: &dyn ZookeeperClient, status: &mut ZookeeperClusterStatus) -> Result<(), Error> {
+    let path = get_meta_path(z);
+    if status.meta_root_created != Some(true) {
+        info!("Creating zookeeper metadata znode {}", path);
+        zk_client.create_node(z, &path).await?;
+        status.meta_root_created = Some(true);
+    }
+
+    let (data, version) = match zk_client.get_node(&path).await {
+        // lost with the data of an ephemeral ensemble, or removed from outside
+        Err(Error::Zookeeper(zookeeper::ZkError::NoNode)) => {
+            warn!("Zookeeper metadata znode {} is gone, recreating it", path);
+            zk_client.create_node(z, &path).await?;
+            zk_client.get_node(&path).await?
+        }
+        result => result?,
+    };
+    let desired = format!("CLUSTER_SIZE={}", z.spec.replicas);
+    if data != desired.as_bytes() {
+        info!("Updating zookeeper metadata znode {} to {}", path, desired);
+        zk_client.update_node(&path, &desired, version).await?;
     }
+    // someone else rewriting or removing the znode should not wait for the next requeue
+    zk_client.watch_node(&path).await?;
+    Ok(())
+}
+
+async fn reconcile_statefulset(z: &ZookeeperCluster, client: Client, replicas: i32, super_digest: Option<&str>) -> Result<StatefulSet, Error> {
+    let sts_api: Api<StatefulSet> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    create_or_apply(&sts_api, generators::make_statefulset(z, replicas, super_digest)).await
+}
 
-    if changed {
-        // todo 
+/// Replica count the StatefulSet should have right now. A new ensemble, or one that has
+/// never been reachable, simply gets `spec.replicas`. Resizing a running one moves a
+/// single ordinal at a time, with the step recorded in `status.scaling`: growing waits
+/// for `add_member` to make the new server a voter, shrinking has `remove_member` take the
+/// highest one out of the config before its pod goes away.
+async fn reconcile_scale(z: &ZookeeperCluster, client: Client, zk_client: Option<&dyn ZookeeperClient>, status: &mut ZookeeperClusterStatus) -> Result<i32, Error> {
+    let current = statefulset_replicas(z, client.clone()).await?;
+    let (current, zk_client) = match (current, zk_client) {
+        (Some(current), Some(zk_client)) => (current, zk_client),
+        _ => return Ok(z.spec.replicas),
+    };
+
+    let mut scaling = match status.scaling.clone() {
+        Some(scaling) => scaling,
+        None if z.spec.replicas > current => {
+            info!("Scaling zookeeper cluster {} up from {} to {} members", z.name_any(), current, z.spec.replicas);
+            // resume from a member whose reconfig was lost along with the status
+            let config = zk_client.get_config().await?;
+            let ordinal = (0..current).find(|&o| !config.is_participant(o + 1)).unwrap_or(current);
+            ScalingStatus { ordinal, ..Default::default() }
+        }
+        None if z.spec.replicas < current => {
+            // the StatefulSet only drops one member at a time, so a pod left at `current`
+            // is the member whose record was lost while it shut down, and the next one
+            // only leaves once it is gone
+            let pod_api: Api<Pod> = Api::namespaced(client.clone(), &z.namespace().unwrap_or_default());
+            if pod_api.get_opt(&z.get_member_name(current)).await?.is_some() {
+                info!("Waiting for zookeeper server {} to shut down before scaling {} further", current + 1, z.name_any());
+                return Ok(current);
+            }
+            info!("Scaling zookeeper cluster {} down from {} to {} members", z.name_any(), current, z.spec.replicas);
+            ScalingStatus { ordinal: current - 1, phase: SCALING_PHASE_REMOVING_MEMBER.to_owned(), ..Default::default() }
+        }
+        None => return Ok(z.spec.replicas),
+    };
+    scaling.target_replicas = z.spec.replicas;
+    // a changed target still lets the member in flight finish joining or leaving
+    let step = if [SCALING_PHASE_REMOVING_MEMBER, SCALING_PHASE_WAITING_FOR_POD_DELETION].contains(&scaling.phase.as_str()) {
+        scale_down_step(z, client, zk_client, &mut scaling).await
+    } else {
+        scale_up_step(z, client, zk_client, &mut scaling).await
+    };
+    let (replicas, finished) = match step {
+        Ok(step) => step,
+        // keep whatever the step got through, so the next reconcile picks up from there
+        Err(e) => {
+            status.scaling = Some(scaling);
+            return Err(e);
+        }
+    };
+    status.scaling = (!finished).then_some(scaling);
+    if finished {
+        info!("Zookeeper cluster {} scaled to {} members", z.name_any(), replicas);
     }
+    Ok(replicas)
+}
 
-    Ok(Action::requeue(Duration::from_secs(300)))
+/// Replica count of the StatefulSet as it stands, None before it exists.
+async fn statefulset_replicas(z: &ZookeeperCluster, client: Client) -> Result<Option<i32>, Error> {
+    let sts_api: Api<StatefulSet> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    Ok(sts_api.get_opt(&z.name_any()).await?.and_then(|sts| sts.spec).and_then(|spec| spec.replicas))
+}
+
+/// Advance a scale up, returning the replica count and whether the scale is done.
+async fn scale_up_step(z: &ZookeeperCluster, client: Client, zk_client: &dyn ZookeeperClient, scaling: &mut ScalingStatus) -> Result<(i32, bool), Error> {
+    match add_member(z, client, zk_client, scaling.ordinal).await? {
+        Some(phase) => scaling.phase = phase.to_owned(),
+        None if scaling.ordinal + 1 >= scaling.target_replicas => return Ok((scaling.ordinal + 1, true)),
+        None => {
+            scaling.ordinal += 1;
+            scaling.phase = SCALING_PHASE_WAITING_FOR_POD.to_owned();
+        }
+    }
+    Ok((scaling.ordinal + 1, false))
+}
+
+/// Advance a scale down, returning the replica count and whether the scale is done. The
+/// StatefulSet only drops the member once the ensemble has committed a config without
+/// it, and the next member is only taken out once the pod is gone. The orphaned volume
+/// is left to `reconcile_volumes`.
+async fn scale_down_step(z: &ZookeeperCluster, client: Client, zk_client: &dyn ZookeeperClient, scaling: &mut ScalingStatus) -> Result<(i32, bool), Error> {
+    let ordinal = scaling.ordinal;
+    if scaling.phase == SCALING_PHASE_REMOVING_MEMBER {
+        if !remove_member(z, zk_client, ordinal).await? {
+            return Ok((ordinal + 1, false));
+        }
+        scaling.phase = SCALING_PHASE_WAITING_FOR_POD_DELETION.to_owned();
+    }
+
+    let pod_api: Api<Pod> = Api::namespaced(client.clone(), &z.namespace().unwrap_or_default());
+    if pod_api.get_opt(&z.get_member_name(ordinal)).await?.is_some() {
+        return Ok((ordinal, false));
+    }
+    if ordinal <= scaling.target_replicas {
+        return Ok((ordinal, true));
+    }
+    scaling.ordinal -= 1;
+    scaling.phase = SCALING_PHASE_REMOVING_MEMBER.to_owned();
+    Ok((ordinal, false))
+}
+
+/// Make the member with `ordinal` a voter once its pod is ready and its server has synced
+/// with the leader up to the current config. Returns the phase it is waiting in, or None
+/// when it is a voter.
+async fn add_member(z: &ZookeeperCluster, client: Client, zk_client: &dyn ZookeeperClient, ordinal: i32) -> Result<Option<&'static str>, Error> {
+    let config = zk_client.get_config().await?;
+    let id = ordinal + 1;
+    if config.is_participant(id) {
+        return Ok(None);
+    }
+
+    let pod_api: Api<Pod> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    if !pod_api.get_opt(&z.get_member_name(ordinal)).await?.is_some_and(|pod| is_pod_ready(&pod)) {
+        return Ok(Some(SCALING_PHASE_WAITING_FOR_POD));
+    }
+    let stats = match zookeeper_health::inspect_member(z, ordinal).await {
+        Ok(stats) => stats,
+        // a server that just started answers that it is not serving requests yet
+        Err(e) => {
+            info!("Waiting for zookeeper server {} to serve: {}", id, e);
+            return Ok(Some(SCALING_PHASE_WAITING_FOR_SYNC));
+        }
+    };
+    if !stats.is_synced() || stats.zxid < config.zxid {
+        info!("Waiting for zookeeper server {} to sync, it is {} at zxid {:#x}", id, stats.mode, stats.zxid);
+        return Ok(Some(SCALING_PHASE_WAITING_FOR_SYNC));
+    }
+
+    info!("Adding zookeeper server {} to the ensemble of {}", id, z.name_any());
+    let config = zk_client.reconfig(vec![generators::make_server_spec(z, ordinal)], vec![], config.version).await?;
+    if !config.is_participant(id) {
+        warn!("Zookeeper server {} is still not a voter after reconfig, retrying", id);
+        return Ok(Some(SCALING_PHASE_WAITING_FOR_SYNC));
+    }
+    Ok(None)
+}
+
+/// Take the member with `ordinal` out of the ensemble config, returning whether the
+/// committed config no longer has it. The last member has no ensemble left to leave.
+async fn remove_member(z: &ZookeeperCluster, zk_client: &dyn ZookeeperClient, ordinal: i32) -> Result<bool, Error> {
+    let id = ordinal + 1;
+    let config = zk_client.get_config().await?;
+    if ordinal == 0 || !config.servers.contains_key(&id) {
+        return Ok(true);
+    }
+
+    info!("Removing zookeeper server {} from the ensemble of {}", id, z.name_any());
+    let config = zk_client.reconfig(vec![], vec![id], config.version).await?;
+    if config.servers.contains_key(&id) {
+        warn!("Zookeeper server {} is still in the config after reconfig, retrying", id);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Deal with data volume claims of ordinals at or above `replicas`, which no member uses
+/// any more: deleted under the Delete reclaim policy, reported in
+/// `status.orphaned_volume_claims` under Retain. Claims of a member still shutting down
+/// are held back by Kubernetes until its pod is gone.
+async fn reconcile_volumes(z: &ZookeeperCluster, client: Client, replicas: i32, status: &mut ZookeeperClusterStatus) -> Result<(), Error> {
+    let orphaned = list_data_volume_claims(z, client.clone()).await?
+        .into_iter()
+        .filter(|(ordinal, _)| *ordinal >= replicas)
+        .map(|(_, pvc)| pvc)
+        .collect::<Vec<_>>();
+    if z.reclaims_volumes() {
+        delete_volume_claims(z, client, orphaned).await?;
+        status.orphaned_volume_claims = vec![];
+    } else {
+        status.orphaned_volume_claims = orphaned.iter().map(|pvc| pvc.name_any()).collect();
+    }
+    Ok(())
+}
+
+/// Data volume claims the StatefulSet created for the cluster, with the ordinal of each.
+async fn list_data_volume_claims(z: &ZookeeperCluster, client: Client) -> Result<Vec<(i32, PersistentVolumeClaim)>, Error> {
+    let pvc_api: Api<PersistentVolumeClaim> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    let lp = ListParams::default().labels(&format!("app={}", z.name_any()));
+    let mut claims = pvc_api.list(&lp).await?
+        .into_iter()
+        .filter_map(|pvc| Some((generators::data_volume_claim_ordinal(z, &pvc.name_any())?, pvc)))
+        .collect::<Vec<_>>();
+    claims.sort_by_key(|(ordinal, _)| *ordinal);
+    Ok(claims)
+}
+
+async fn delete_volume_claims(z: &ZookeeperCluster, client: Client, claims: Vec<PersistentVolumeClaim>) -> Result<(), Error> {
+    let pvc_api: Api<PersistentVolumeClaim> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    for pvc in claims.iter().filter(|pvc| pvc.metadata.deletion_timestamp.is_none()) {
+        info!("Deleting PersistentVolumeClaim {}/{}", z.namespace().unwrap_or_default(), pvc.name_any());
+        pvc_api.delete(&pvc.name_any(), &DeleteParams::default()).await?;
+    }
+    Ok(())
+}
+
+/// Record replica counts from the StatefulSet and which member pods are ready.
+async fn observe_members(z: &ZookeeperCluster, client: Client, sts: &StatefulSet, status: &mut ZookeeperClusterStatus) -> Result<(), Error> {
+    let sts_status = sts.status.clone().unwrap_or_default();
+    status.replicas = Some(sts_status.replicas);
+    status.ready_replicas = Some(sts_status.ready_replicas.unwrap_or(0));
+
+    let pod_api: Api<Pod> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    let lp = ListParams::default().labels(&format!("app={}", z.name_any()));
+    let mut members = MembersStatus::default();
+    for pod in pod_api.list(&lp).await? {
+        if is_pod_ready(&pod) {
+            members.ready.push(pod.name_any());
+        } else {
+            members.unready.push(pod.name_any());
+        }
+    }
+    members.ready.sort();
+    members.unready.sort();
+
+    // counters like the zxid change with every request, so they are only sampled every
+    // SERVER_STATS_INTERVAL rather than written, and reconciled again, on every pass
+    let previous = status.members.take().unwrap_or_default();
+    let fresh = previous.servers_update_time.as_ref()
+        .and_then(|t| (Utc::now() - t.0).to_std().ok())
+        .map(|age| age < SERVER_STATS_INTERVAL)
+        .unwrap_or(false);
+    if fresh {
+        members.servers = previous.servers.into_iter().filter(|s| members.ready.contains(&s.name)).collect();
+        members.servers_update_time = previous.servers_update_time;
+        status.members = Some(members);
+        return Ok(());
+    }
+    // an unreachable server is left out rather than failing the reconcile
+    let inspections = members.ready.iter()
+        .filter_map(|name| z.get_member_ordinal(name))
+        .map(|ordinal| async move { zookeeper_health::inspect_member(z, ordinal).await.map(|stats| (ordinal, stats)) });
+    for inspection in futures::future::join_all(inspections).await {
+        match inspection {
+            Ok((ordinal, stats)) => members.servers.push(ServerStatus {
+                name: z.get_member_name(ordinal),
+                role: stats.mode,
+                last_zxid: format!("{:#x}", stats.zxid),
+                connections: stats.connections,
+                outstanding_requests: stats.outstanding_requests,
+                latency: stats.latency,
+            }),
+            Err(e) => debug!("Leaving a member of {} out of the status: {}", z.name_any(), e),
+        }
+    }
+    members.servers_update_time = Some(Time(Utc::now()));
+    status.members = Some(members);
+    Ok(())
+}
+
+/// Derive PodsReady from the ready replica count.
+fn update_conditions(z: &ZookeeperCluster, sts: &StatefulSet, status: &mut ZookeeperClusterStatus) {
+    let sts_status = match sts.status.as_ref() {
+        Some(sts_status) => sts_status,
+        None => {
+            status.set_condition(ClusterConditionType::PodsReady, CONDITION_UNKNOWN, "", "");
+            return;
+        }
+    };
+
+    let ready = sts_status.ready_replicas.unwrap_or(0);
+    if ready == z.spec.replicas && sts_status.replicas == z.spec.replicas {
+        status.set_condition(ClusterConditionType::PodsReady, CONDITION_TRUE, "", "");
+    } else {
+        let message = format!("{} of {} pods ready", ready, z.spec.replicas);
+        status.set_condition(ClusterConditionType::PodsReady, CONDITION_FALSE, "PodsNotReady", &message);
+    }
+}
+
+/// Track image version changes and decide which version the member pods should run.
+/// A new image tag starts an upgrade if the compatibility table allows the jump from the
+/// current version; one that does not, or an upgrade that failed and was rolled back,
+/// keeps the ensemble on the current version until the tag changes again.
+fn plan_upgrade(z: &ZookeeperCluster, status: &mut ZookeeperClusterStatus) -> String {
+    let version = z.spec.image.as_ref().and_then(|i| i.tag.clone()).unwrap_or_default();
+    let current_version = status.current_version.get_or_insert_with(|| version.clone()).clone();
+    if version == current_version {
+        if status.target_version.take().is_some() {
+            // rolling on brings members already upgraded back to the current version
+            info!("Upgrade of zookeeper cluster {} abandoned, staying on {}", z.name_any(), current_version);
+        }
+        if upgrade_blocked(status).is_some() {
+            status.set_condition(ClusterConditionType::Upgrading, CONDITION_FALSE, "", "");
+        }
+        return version;
+    }
+    if status.target_version.as_deref() == Some(version.as_str()) {
+        let failed = status.get_condition(ClusterConditionType::Upgrading)
+            .and_then(|c| c.reason.as_deref())
+            == Some(UPGRADE_FAILED_REASON);
+        return if failed { current_version } else { version };
+    }
+    status.target_version = None;
+    if let Err(why) = zookeeper_version::check_upgrade(&current_version, &version) {
+        warn!("Refusing to upgrade zookeeper cluster {}: {}", z.name_any(), why);
+        let message = format!("Staying on {}: {}", current_version, why);
+        status.set_condition(ClusterConditionType::Upgrading, CONDITION_FALSE, UPGRADE_NOT_SUPPORTED_REASON, &message);
+        return current_version;
+    }
+    info!("Upgrading zookeeper cluster {} from {} to {}", z.name_any(), current_version, version);
+    status.target_version = Some(version.clone());
+    let message = format!("Upgrading from {} to {}", current_version, version);
+    status.set_condition(ClusterConditionType::Upgrading, CONDITION_TRUE, UPGRADE_IN_PROGRESS_REASON, &message);
+    version
+}
+
+/// Why the image tag in the spec is not being rolled out, if the upgrade to it was
+/// refused or has failed.
+fn upgrade_blocked(status: &ZookeeperClusterStatus) -> Option<String> {
+    status.get_condition(ClusterConditionType::Upgrading)
+        .filter(|c| [Some(UPGRADE_FAILED_REASON), Some(UPGRADE_NOT_SUPPORTED_REASON)].contains(&c.reason.as_deref()))
+        .map(|c| c.message.clone().unwrap_or_default())
+}
+
+/// Replace the member pods the StatefulSet has not updated, which its OnDelete strategy
+/// leaves to the operator. One pod goes at a time, in the order `next_to_roll` picks and
+/// only while the ensemble can spare it. A version upgrade that makes no progress for
+/// the upgrade timeout is failed, and the next reconcile rolls the members back to the
+/// current version.
+async fn reconcile_rollout(z: &ZookeeperCluster, client: Client, sts: &StatefulSet, status: &mut ZookeeperClusterStatus) -> Result<(), Error> {
+    let upgrading = status.target_version.is_some() && upgrade_blocked(status).is_none();
+    rollout_step(z, client, sts, status, upgrading).await?;
+    // a refused upgrade is reported by its own condition, the ensemble itself is fine
+    match status.get_condition(ClusterConditionType::Upgrading) {
+        Some(c) if c.reason.as_deref() == Some(UPGRADE_FAILED_REASON) => {
+            Err(Error::UpgradeFailed(c.message.clone().unwrap_or_default()))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn rollout_step(z: &ZookeeperCluster, client: Client, sts: &StatefulSet, status: &mut ZookeeperClusterStatus, upgrading: bool) -> Result<(), Error> {
+    // membership changes and restarts do not mix
+    if status.scaling.is_some() {
+        return Ok(());
+    }
+    let update_revision = match sts.status.as_ref().and_then(|s| s.update_revision.clone()) {
+        Some(update_revision) => update_revision,
+        None => return Ok(()),
+    };
+    // a refused or failed upgrade keeps its condition while the members roll
+    let owns_condition = upgrade_blocked(status).is_none();
+
+    let pod_api: Api<Pod> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    let lp = ListParams::default().labels(&format!("app={}", z.name_any()));
+    let pods = pod_api.list(&lp).await?.items;
+    let outdated = pods.iter()
+        .filter(|pod| pod.labels().get(REVISION_LABEL) != Some(&update_revision))
+        .filter_map(|pod| z.get_member_ordinal(&pod.name_any()))
+        .collect::<Vec<_>>();
+    if outdated.is_empty() {
+        if pods.len() as i32 == z.spec.replicas && pods.iter().all(is_pod_ready) && owns_condition {
+            if let Some(target_version) = status.target_version.take() {
+                info!("Zookeeper cluster {} upgraded to {}", z.name_any(), target_version);
+                status.current_version = Some(target_version);
+            }
+            status.set_condition(ClusterConditionType::Upgrading, CONDITION_FALSE, "", "");
+        }
+        return Ok(());
+    }
+
+    let current_version = status.current_version.clone().unwrap_or_default();
+    let updated = pods.len() - outdated.len();
+    let (reason, message) = match &status.target_version {
+        Some(target_version) if upgrading => (
+            UPGRADE_IN_PROGRESS_REASON,
+            format!("Upgrading from {} to {}: {} of {} members updated", current_version, target_version, updated, pods.len()),
+        ),
+        _ => ("RollingUpdate", format!("{} of {} members updated", updated, pods.len())),
+    };
+    let (members, problems) = inspect_members(z, &pods).await;
+    let replaceable = pods.iter()
+        .filter(|pod| pod.metadata.deletion_timestamp.is_none())
+        .filter_map(|pod| z.get_member_ordinal(&pod.name_any()))
+        .filter(|o| outdated.contains(o))
+        .collect::<Vec<_>>();
+    let ordinal = match next_to_roll(z, &replaceable, &members, &problems) {
+        Ok(ordinal) => ordinal,
+        Err(problem) if upgrading && upgrade_stalled(status, z.get_upgrade_timeout()) => {
+            let target_version = status.target_version.clone().unwrap_or_default();
+            let message = format!(
+                "Upgrade to {} made no progress in {:?}: {}; rolling back to {}",
+                target_version, z.get_upgrade_timeout(), problem, current_version
+            );
+            warn!("Zookeeper cluster {}: {}", z.name_any(), message);
+            status.set_condition(ClusterConditionType::Upgrading, CONDITION_FALSE, UPGRADE_FAILED_REASON, &message);
+            return Ok(());
+        }
+        Err(problem) => {
+            info!("Holding the roll of zookeeper cluster {} until the ensemble is healthy: {}", z.name_any(), problem);
+            if owns_condition {
+                status.set_condition(ClusterConditionType::Upgrading, CONDITION_TRUE, reason, &message);
+            }
+            return Ok(());
+        }
+    };
+
+    let name = z.get_member_name(ordinal);
+    info!("Restarting zookeeper member {} to move it to revision {}", name, update_revision);
+    pod_api.delete(&name, &DeleteParams::default()).await?;
+    if owns_condition {
+        status.set_condition(ClusterConditionType::Upgrading, CONDITION_TRUE, reason, &message);
+    }
+    Ok(())
+}
+
+/// Which of the `outdated` members to replace next, given the `members` serving as part
+/// of the quorum and what is wrong with the others, or why none may go yet. With every
+/// member healthy the leader goes last so the ensemble elects once, the least busy
+/// followers first and the highest ordinal first among equals, as the StatefulSet
+/// would. An outdated member that is down costs nothing to replace while the others
+/// keep a quorum, which is also how a rollback gets past a member crash looping on the
+/// version it rolls back from.
+fn next_to_roll(z: &ZookeeperCluster, outdated: &[i32], members: &BTreeMap<i32, ServerStats>, problems: &[String]) -> Result<i32, String> {
+    let healthy = (0..z.spec.replicas).all(|o| members.contains_key(&o));
+    if healthy && problems.is_empty() {
+        return outdated.iter().copied()
+            .min_by_key(|o| {
+                let stats = &members[o];
+                (stats.is_leader(), stats.outstanding_requests, std::cmp::Reverse(*o))
+            })
+            .ok_or_else(|| "no member is outdated".to_owned());
+    }
+    let quorum = z.spec.replicas / 2 + 1;
+    match outdated.iter().copied().filter(|o| !members.contains_key(o)).max() {
+        Some(ordinal) if members.len() as i32 >= quorum => Ok(ordinal),
+        _ if problems.is_empty() => Err(format!("{} of {} members serve", members.len(), z.spec.replicas)),
+        _ => Err(problems.join("; ")),
+    }
+}
+
+/// What each member serving as part of the quorum reports about itself, and what is
+/// wrong with the others.
+async fn inspect_members(z: &ZookeeperCluster, pods: &[Pod]) -> (BTreeMap<i32, ServerStats>, Vec<String>) {
+    let mut members = BTreeMap::new();
+    let mut problems = vec![];
+    if pods.len() as i32 != z.spec.replicas {
+        problems.push(format!("{} of {} member pods exist", pods.len(), z.spec.replicas));
+    }
+    for pod in pods {
+        let ordinal = match z.get_member_ordinal(&pod.name_any()) {
+            Some(ordinal) => ordinal,
+            None => continue,
+        };
+        if pod.metadata.deletion_timestamp.is_some() {
+            problems.push(format!("{} is terminating", pod.name_any()));
+            continue;
+        }
+        if !is_pod_ready(pod) {
+            problems.push(format!("{} is not ready", pod.name_any()));
+            continue;
+        }
+        match zookeeper_health::inspect_member(z, ordinal).await {
+            Ok(stats) if stats.is_synced() => {
+                members.insert(ordinal, stats);
+            }
+            Ok(stats) => problems.push(format!("{} is {}", pod.name_any(), stats.mode)),
+            Err(e) => problems.push(e.to_string()),
+        }
+    }
+    (members, problems)
+}
+
+/// Whether the upgrade has gone `timeout` without another member updating.
+fn upgrade_stalled(status: &ZookeeperClusterStatus, timeout: Duration) -> bool {
+    status.get_condition(ClusterConditionType::Upgrading)
+        .and_then(|c| c.last_update_time.as_ref())
+        .and_then(|t| (Utc::now() - t.0).to_std().ok())
+        .map(|elapsed| elapsed > timeout)
+        .unwrap_or(false)
+}
+
+fn is_pod_ready(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .map(|conditions| conditions.iter().any(|c| c.type_ == "Ready" && c.status == "True"))
+        .unwrap_or(false)
+}
+
+async fn reconcile_services(z: &ZookeeperCluster, client: Client, status: &mut ZookeeperClusterStatus) -> Result<(), Error> {
+    let svc_api: Api<Service> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    create_or_apply(&svc_api, generators::make_headless_service(z)).await?;
+    create_or_apply(&svc_api, generators::make_admin_server_service(z)).await?;
+    let client_svc = create_or_apply(&svc_api, generators::make_client_service(z)).await?;
+    let cluster_ip = client_svc.spec.as_ref().and_then(|s| s.cluster_ip.clone());
+    status.internal_client_endpoint = cluster_ip
+        .map(|ip| format!("{}:{}", ip, z.get_port("client", 2181)));
+
+    match generators::make_external_client_service(z) {
+        Some(desired) => {
+            let external_svc = create_or_apply(&svc_api, desired).await?;
+            status.external_client_endpoint = external_client_endpoint(&external_svc);
+        }
+        None => {
+            let name = z.get_external_client_service_name();
+            if svc_api.get_opt(&name).await?.is_some() {
+                info!("Deleting external client Service {}/{}", z.namespace().unwrap_or_default(), name);
+                svc_api.delete(&name, &DeleteParams::default()).await?;
+            }
+            status.external_client_endpoint = None;
+        }
+    }
+    Ok(())
+}
+
+/// Address clients outside the cluster should use: the LoadBalancer ingress once it is
+/// assigned. A NodePort Service is reached through any node's address, which is no single
+/// endpoint, so none is published for it.
+fn external_client_endpoint(svc: &Service) -> Option<String> {
+    let spec = svc.spec.as_ref()?;
+    if spec.type_.as_deref() == Some(zookeeper_type::SERVICE_TYPE_NODE_PORT) {
+        return None;
+    }
+    let port = spec.ports.as_ref()?.first()?;
+    let ingress = svc.status.as_ref()?.load_balancer.as_ref()?.ingress.as_ref()?.first()?;
+    let host = ingress.ip.clone().or_else(|| ingress.hostname.clone())?;
+    Some(format!("{}:{}", host, port.port))
+}
+
+async fn reconcile_pod_disruption_budget(z: &ZookeeperCluster, client: Client) -> Result<(), Error> {
+    let pdb_api: Api<PodDisruptionBudget> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    create_or_apply(&pdb_api, generators::make_pod_disruption_budget(z)).await?;
+    Ok(())
+}
+
+/// Write the observed status back through the status subresource when it changed.
+async fn reconcile_cluster_status(z: &ZookeeperCluster, client: Client, status: ZookeeperClusterStatus) -> Result<(), Error> {
+    if z.status.as_ref() == Some(&status) {
+        return Ok(());
+    }
+    let zk_api: Api<ZookeeperCluster> = Api::namespaced(client, &z.namespace().unwrap_or_default());
+    let patch = serde_json::json!({
+        "apiVersion": ZookeeperCluster::api_version(&()),
+        "kind": ZookeeperCluster::kind(&()),
+        "status": serde_json::to_value(&status)?,
+    });
+    let pp = PatchParams::apply(FIELD_MANAGER).force();
+    zk_api.patch_status(&z.name_any(), &pp, &Patch::Apply(&patch)).await?;
+    Ok(())
+}
+
+/// Create an owned object if it is missing, or server-side apply the generated one when
+/// its spec hash no longer matches what was last applied. Returns the live object.
+async fn create_or_apply<K>(api: &Api<K>, desired: K) -> Result<K, Error>
+where
+    K: Resource + Clone + Serialize + DeserializeOwned + Debug,
+    K::DynamicType: Default,
+{
+    let kind = K::kind(&K::DynamicType::default()).to_string();
+    let name = desired.name_any();
+    match api.get_opt(&name).await? {
+        None => {
+            info!("Creating a new {} {}/{}", kind, desired.namespace().unwrap_or_default(), name);
+            Ok(api.create(&PostParams::default(), &desired).await?)
+        }
+        Some(found) => {
+            if generators::recorded_spec_hash(found.meta()) == generators::recorded_spec_hash(desired.meta()) {
+                return Ok(found);
+            }
+            info!("Updating existing {} {}/{}", kind, desired.namespace().unwrap_or_default(), name);
+            let pp = PatchParams::apply(FIELD_MANAGER).force();
+            Ok(api.patch(&name, &pp, &Patch::Apply(&desired)).await?)
+        }
+    }
 }
 
 /// object that caused the failure and the actual error
-fn error_policy(obj: Arc<ZookeeperCluster>, _error: &Error, _ctx: Arc<ZookeeperClusterReconciler>) -> Action {
-    Action::requeue(Duration::from_secs(60))
+/// The cluster an ensemble event came from, which the client pool keys by namespace/name.
+fn cluster_ref(event: &ZookeeperEvent) -> Option<ObjectRef<ZookeeperCluster>> {
+    let (namespace, name) = event.cluster.split_once('/')?;
+    Some(ObjectRef::new(name).within(namespace))
+}
+
+fn error_policy(obj: Arc<ZookeeperCluster>, error: &Error, _ctx: Arc<ZookeeperClusterReconciler>) -> Action {
+    warn!("reconcile of {}/{} failed: {}", obj.namespace().unwrap_or_default(), obj.name_any(), error);
+    match error {
+        // a conflict only means our copy was stale, retry right away
+        Error::Kube(kube::Error::Api(ae)) if ae.code == 409 => Action::requeue(Duration::from_secs(1)),
+        Error::Kube(_) => Action::requeue(Duration::from_secs(15)),
+        // the secret may simply not have been created yet
+        Error::Zookeeper(_) | Error::ZookeeperTimeout(_) | Error::Inspection(..) | Error::Credentials(_) => Action::requeue(RECONCILE_TIME),
+        // nothing changes until someone edits the resource, which triggers a reconcile anyway
+        Error::InvalidSpec(_) | Error::Serialization(_) | Error::UpgradeFailed(_) => Action::requeue(Duration::from_secs(300)),
+    }
 }
 
 
@@ -113,22 +928,274 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init(); // init logging
     let client = Client::try_default().await?;
     let zk_cluster = Api::<ZookeeperCluster>::all(client.clone());
-    let zk_client = zk::DefaultZookeeperClient::new("localhost:2181");
+    let (zk_events, zk_event_stream) = futures::channel::mpsc::unbounded();
+    let zk_clients = Arc::new(ZookeeperClientPool::new(zk_events));
     let pods = Api::<Pod>::all(client.clone());
+    let statefulsets = Api::<StatefulSet>::all(client.clone());
+    let config_maps = Api::<ConfigMap>::all(client.clone());
+    let services = Api::<Service>::all(client.clone());
+    let pdbs = Api::<PodDisruptionBudget>::all(client.clone());
+
+    let context = Arc::new(ZookeeperClusterReconciler{ client, zk_clients: zk_clients.clone() }); // context with zookeeperclusterReconciler
 
-    let log = tracing_subscriber::fmt::Subscriber::new();
-    let context = Arc::new(ZookeeperClusterReconciler{ client, log, zk_client }); // context with zookeeperclusterReconciler
 
+    // what Controller::run builds, plus ensemble events, which only concern the cluster
+    // they came from and so get queued for it alone
+    let (store, writer) = reflector::store();
+    let mut triggers = stream::SelectAll::new();
+    triggers.push(trigger_self(reflector(writer, watcher(zk_cluster, ListParams::default())).applied_objects(), ()).boxed());
+    triggers.push(trigger_owners(watcher(pods, ListParams::default()).touched_objects(), (), ()).boxed());
+    triggers.push(trigger_owners(watcher(statefulsets, ListParams::default()).touched_objects(), (), ()).boxed());
+    triggers.push(trigger_owners(watcher(config_maps, ListParams::default()).touched_objects(), (), ()).boxed());
+    triggers.push(trigger_owners(watcher(services, ListParams::default()).touched_objects(), (), ()).boxed());
+    triggers.push(trigger_owners(watcher(pdbs, ListParams::default()).touched_objects(), (), ()).boxed());
+    triggers.push(zk_event_stream
+        .filter_map(|event| async move {
+            info!("zookeeper event {:?}, reconciling", event);
+            Some(Ok::<_, watcher::Error>(ReconcileRequest::from(cluster_ref(&event)?)))
+        })
+        .boxed());
 
-    Controller::new(zk_cluster, ListParams::default())
-        .owns(pods, ListParams::default())
-        .run(reconcile, error_policy, context)
-        .for_each(|res| async move {
-            match res {
-                Ok(o) => println!("reconciled {:?}", o),
-                Err(e) => println!("reconcile failed: {:?}", e),
+    applier(
+        |z, ctx| CancelableJoinHandle::spawn(reconcile(z, ctx), &Handle::current()),
+        error_policy,
+        context,
+        store,
+        StreamBackoff::new(triggers, watcher::default_backoff()),
+    )
+        .for_each(|res| {
+            let zk_clients = zk_clients.clone();
+            async move {
+                match res {
+                    Ok(o) => println!("reconciled {:?}", o),
+                    // the cluster is gone, so is any reason to keep talking to its ensemble
+                    Err(controller::Error::ObjectNotFound(obj)) => {
+                        zk_clients.remove(&obj.namespace.unwrap_or_default(), &obj.name).await;
+                    }
+                    Err(e) => println!("reconcile failed: {:?}", e),
+                }
             }
         })
         .await; // controller does nothing unless polled
     Ok(())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zookeeper_client_fake::InMemoryZookeeperClient;
+    use zookeeper_client_go::ZookeeperEventKind;
+    use k8s_openapi::ByteString;
+    use zookeeper_type::ContainerImage;
+
+    fn cluster(replicas: i32) -> ZookeeperCluster {
+        let mut z = ZookeeperCluster::new("zk", Default::default());
+        z.spec.replicas = replicas;
+        z
+    }
+
+    fn cluster_on(tag: &str) -> ZookeeperCluster {
+        let mut z = cluster(3);
+        z.spec.image = Some(ContainerImage { tag: Some(tag.to_owned()), ..Default::default() });
+        z
+    }
+
+    fn upgrading_reason(status: &ZookeeperClusterStatus) -> Option<&str> {
+        status.get_condition(ClusterConditionType::Upgrading).and_then(|c| c.reason.as_deref())
+    }
+
+    fn serving(modes: &[(i32, &str, i64)]) -> BTreeMap<i32, ServerStats> {
+        modes.iter()
+            .map(|&(ordinal, mode, outstanding_requests)| {
+                (ordinal, ServerStats { mode: mode.to_owned(), outstanding_requests, ..Default::default() })
+            })
+            .collect()
+    }
+
+    fn secret(data: &[(&str, &[u8])]) -> Secret {
+        Secret {
+            data: Some(data.iter().map(|&(key, value)| (key.to_owned(), ByteString(value.to_vec()))).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn credentials_come_from_the_secret() {
+        let credentials = credentials_from_secret("zk-auth", Some(secret(&[("username", b"operator"), ("password", b"secret")])));
+        assert_eq!(credentials.unwrap(), DigestCredentials { username: "operator".to_owned(), password: "secret".to_owned() });
+    }
+
+    #[test]
+    fn credentials_need_a_complete_secret() {
+        let error = |secret| match credentials_from_secret("zk-auth", secret) {
+            Err(Error::Credentials(message)) => message,
+            other => panic!("expected a credentials error, got {:?}", other),
+        };
+        assert_eq!(error(None), "secret zk-auth not found");
+        assert_eq!(error(Some(secret(&[("username", b"operator")]))), "secret zk-auth has no \"password\" key");
+        assert_eq!(
+            error(Some(secret(&[("username", b"\xff\xfe"), ("password", b"secret")]))),
+            "secret zk-auth key \"username\" is not valid UTF-8"
+        );
+    }
+
+    #[test]
+    fn ensemble_events_target_their_cluster() {
+        let event = |cluster: &str| ZookeeperEvent { cluster: cluster.to_owned(), kind: ZookeeperEventKind::Expired };
+        assert_eq!(cluster_ref(&event("team-a/zk")), Some(ObjectRef::new("zk").within("team-a")));
+        assert_eq!(cluster_ref(&event("zk")), None);
+    }
+
+    #[tokio::test]
+    async fn cluster_metadata_is_created_once() {
+        let zk_client = InMemoryZookeeperClient::new();
+        let mut status = ZookeeperClusterStatus::default();
+        reconcile_cluster_metadata(&cluster(3), &zk_client, &mut status).await.unwrap();
+        reconcile_cluster_metadata(&cluster(3), &zk_client, &mut status).await.unwrap();
+
+        assert_eq!(status.meta_root_created, Some(true));
+        assert_eq!(zk_client.get_node("/zookeeper-operator/zk").await.unwrap(), (b"CLUSTER_SIZE=3".to_vec(), 0));
+    }
+
+    #[tokio::test]
+    async fn cluster_metadata_follows_replicas() {
+        let zk_client = InMemoryZookeeperClient::new();
+        let mut status = ZookeeperClusterStatus::default();
+        reconcile_cluster_metadata(&cluster(3), &zk_client, &mut status).await.unwrap();
+        reconcile_cluster_metadata(&cluster(5), &zk_client, &mut status).await.unwrap();
+
+        assert_eq!(zk_client.get_node("/zookeeper-operator/zk").await.unwrap(), (b"CLUSTER_SIZE=5".to_vec(), 1));
+    }
+
+    #[tokio::test]
+    async fn cluster_metadata_is_recreated_after_a_delete() {
+        let zk_client = InMemoryZookeeperClient::new();
+        let mut status = ZookeeperClusterStatus::default();
+        reconcile_cluster_metadata(&cluster(3), &zk_client, &mut status).await.unwrap();
+        zk_client.delete_node("/zookeeper-operator/zk", None).await.unwrap();
+        reconcile_cluster_metadata(&cluster(3), &zk_client, &mut status).await.unwrap();
+
+        assert_eq!(status.meta_root_created, Some(true));
+        assert_eq!(zk_client.get_node("/zookeeper-operator/zk").await.unwrap(), (b"CLUSTER_SIZE=3".to_vec(), 0));
+    }
+
+    /// An ensemble of `replicas` voters, as the scaling code sees it through the fake.
+    async fn ensemble(z: &ZookeeperCluster, replicas: i32) -> InMemoryZookeeperClient {
+        let zk_client = InMemoryZookeeperClient::new();
+        let servers = (0..replicas).map(|ordinal| generators::make_server_spec(z, ordinal)).collect();
+        zk_client.reconfig(servers, vec![], -1).await.unwrap();
+        zk_client
+    }
+
+    #[tokio::test]
+    async fn add_member_leaves_a_voter_alone() {
+        let z = cluster(3);
+        let zk_client = ensemble(&z, 3).await;
+        // never reached, as a member that already votes needs no pod or server check
+        let client = Client::try_from(kube::Config::new("http://127.0.0.1:1".parse().unwrap())).unwrap();
+
+        assert_eq!(add_member(&z, client, &zk_client, 2).await.unwrap(), None);
+        assert_eq!(zk_client.get_config().await.unwrap().version, 1);
+    }
+
+    #[tokio::test]
+    async fn remove_member_takes_the_server_out() {
+        let z = cluster(2);
+        let zk_client = ensemble(&z, 3).await;
+
+        assert!(remove_member(&z, &zk_client, 2).await.unwrap());
+        let config = zk_client.get_config().await.unwrap();
+        assert_eq!(config.servers.keys().copied().collect::<Vec<_>>(), vec![1, 2]);
+        // gone already, so there is nothing left to reconfig
+        assert!(remove_member(&z, &zk_client, 2).await.unwrap());
+        assert_eq!(zk_client.get_config().await.unwrap().version, config.version);
+    }
+
+    #[tokio::test]
+    async fn remove_member_keeps_the_last_member() {
+        let z = cluster(0);
+        let zk_client = ensemble(&z, 1).await;
+
+        assert!(remove_member(&z, &zk_client, 0).await.unwrap());
+        assert!(zk_client.get_config().await.unwrap().is_participant(1));
+    }
+
+    #[tokio::test]
+    async fn remove_member_retries_a_reconfig_that_kept_the_server() {
+        let z = cluster(2);
+        let zk_client = ensemble(&z, 3).await;
+        zk_client.keep_server(3);
+
+        assert!(!remove_member(&z, &zk_client, 2).await.unwrap());
+        assert!(zk_client.get_config().await.unwrap().servers.contains_key(&3));
+    }
+
+    #[test]
+    fn plan_upgrade_adopts_the_first_version_seen() {
+        let mut status = ZookeeperClusterStatus::default();
+        assert_eq!(plan_upgrade(&cluster_on("3.6.3"), &mut status), "3.6.3");
+        assert_eq!(status.current_version.as_deref(), Some("3.6.3"));
+        assert_eq!(status.target_version, None);
+    }
+
+    #[test]
+    fn plan_upgrade_starts_a_supported_upgrade() {
+        let mut status = ZookeeperClusterStatus { current_version: Some("3.5.9".to_owned()), ..Default::default() };
+        assert_eq!(plan_upgrade(&cluster_on("3.6.3"), &mut status), "3.6.3");
+        assert_eq!(status.target_version.as_deref(), Some("3.6.3"));
+        assert_eq!(upgrading_reason(&status), Some(UPGRADE_IN_PROGRESS_REASON));
+        assert!(status.is_true(ClusterConditionType::Upgrading));
+    }
+
+    #[test]
+    fn plan_upgrade_refuses_an_unsupported_jump() {
+        let mut status = ZookeeperClusterStatus { current_version: Some("3.4.14".to_owned()), ..Default::default() };
+        assert_eq!(plan_upgrade(&cluster_on("3.6.3"), &mut status), "3.4.14");
+        assert_eq!(status.target_version, None);
+        assert_eq!(upgrading_reason(&status), Some(UPGRADE_NOT_SUPPORTED_REASON));
+        assert!(!status.is_true(ClusterConditionType::Upgrading));
+    }
+
+    #[test]
+    fn plan_upgrade_rolls_a_failed_upgrade_back_until_the_tag_changes() {
+        let mut status = ZookeeperClusterStatus {
+            current_version: Some("3.5.9".to_owned()),
+            target_version: Some("3.6.3".to_owned()),
+            ..Default::default()
+        };
+        status.set_condition(ClusterConditionType::Upgrading, CONDITION_FALSE, UPGRADE_FAILED_REASON, "stalled");
+        assert_eq!(plan_upgrade(&cluster_on("3.6.3"), &mut status), "3.5.9");
+        assert_eq!(upgrading_reason(&status), Some(UPGRADE_FAILED_REASON));
+
+        assert_eq!(plan_upgrade(&cluster_on("3.5.9"), &mut status), "3.5.9");
+        assert_eq!(status.target_version, None);
+        assert_eq!(upgrading_reason(&status), Some(""));
+    }
+
+    #[test]
+    fn next_to_roll_leaves_the_leader_last() {
+        let z = cluster(3);
+        let members = serving(&[(0, "follower", 0), (1, "leader", 0), (2, "follower", 0)]);
+        assert_eq!(next_to_roll(&z, &[0, 1, 2], &members, &[]), Ok(2));
+        assert_eq!(next_to_roll(&z, &[0, 1], &members, &[]), Ok(0));
+        assert_eq!(next_to_roll(&z, &[1], &members, &[]), Ok(1));
+
+        let busy = serving(&[(0, "follower", 0), (1, "leader", 0), (2, "follower", 7)]);
+        assert_eq!(next_to_roll(&z, &[0, 1, 2], &busy, &[]), Ok(0));
+    }
+
+    #[test]
+    fn next_to_roll_replaces_a_down_outdated_member_while_a_quorum_serves() {
+        let z = cluster(3);
+        let members = serving(&[(0, "follower", 0), (1, "leader", 0)]);
+        let problems = vec!["zk-2 is not ready".to_owned()];
+        // e.g. rolling back from a version zk-2 crash loops on
+        assert_eq!(next_to_roll(&z, &[2], &members, &problems), Ok(2));
+        // a down member that is already updated has to come up first
+        assert_eq!(next_to_roll(&z, &[0], &members, &problems), Err("zk-2 is not ready".to_owned()));
+
+        let minority = serving(&[(0, "leader", 0)]);
+        assert!(next_to_roll(&z, &[1, 2], &minority, &problems).is_err());
+    }
+}
@@ -1,75 +1,246 @@
 use serde::{Deserialize, Serialize};
 use schemars::JsonSchema;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::chrono::Utc;
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ZookeeperClusterStatus {
     #[serde(rename = "members", skip_serializing_if = "Option::is_none")]
-    members: Option<MembersStatus>,
+    pub members: Option<MembersStatus>,
 
     #[serde(rename = "replicas", skip_serializing_if = "Option::is_none")]
-    replicas: Option<i32>,
+    pub replicas: Option<i32>,
 
     #[serde(rename = "readyReplicas", skip_serializing_if = "Option::is_none")]
-    ready_replicas: Option<i32>,
+    pub ready_replicas: Option<i32>,
 
     #[serde(rename = "internalClientEndpoint", skip_serializing_if = "Option::is_none")]
-    internal_client_endpoint: Option<String>,
+    pub internal_client_endpoint: Option<String>,
 
     #[serde(rename = "externalClientEndpoint", skip_serializing_if = "Option::is_none")]
-    external_client_endpoint: Option<String>,
+    pub external_client_endpoint: Option<String>,
 
     #[serde(rename = "metaRootCreated", skip_serializing_if = "Option::is_none")]
-    meta_root_created: Option<bool>,
+    pub meta_root_created: Option<bool>,
 
     #[serde(rename = "currentVersion", skip_serializing_if = "Option::is_none")]
-    current_version: Option<String>,
+    pub current_version: Option<String>,
 
     #[serde(rename = "targetVersion", skip_serializing_if = "Option::is_none")]
-    target_version: Option<String>,
+    pub target_version: Option<String>,
 
-    #[serde(rename = "conditions", skip_serializing_if = "Vec::is_empty")]
-    conditions: Vec<ClusterCondition>,
+    #[serde(rename = "conditions", default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<ClusterCondition>,
+
+    /// Data volume claims of removed members kept under the Retain reclaim policy.
+    #[serde(rename = "orphanedVolumeClaims", default, skip_serializing_if = "Vec::is_empty")]
+    pub orphaned_volume_claims: Vec<String>,
+
+    /// Progress of an ensemble resize, so an interrupted one picks up where it stopped.
+    #[serde(rename = "scaling", skip_serializing_if = "Option::is_none")]
+    pub scaling: Option<ScalingStatus>,
 }
 
+/// Members are added or removed one at a time. Growing, the StatefulSet comes to include
+/// `ordinal`, then the new server is reconfigured in as a voter once it is ready and
+/// synced. Shrinking, the server is reconfigured out before the StatefulSet drops it.
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct MembersStatus {
-    #[serde(rename = "ready", skip_serializing_if = "Vec::is_empty")]
-    ready: Vec<String>,
+pub struct ScalingStatus {
+    #[serde(rename = "targetReplicas")]
+    pub target_replicas: i32,
 
-    #[serde(rename = "unready", skip_serializing_if = "Vec::is_empty")]
-    unready: Vec<String>,
+    /// Ordinal of the member currently being added or removed.
+    #[serde(rename = "ordinal")]
+    pub ordinal: i32,
+
+    #[serde(rename = "phase")]
+    pub phase: String,
 }
 
-const CONDITION_TRUE: &str = "True";
-const CONDITION_FALSE: &str = "False";
-const CONDITION_UNKNOWN: &str  = "Unknown";
+/// Upgrading condition reasons for a version upgrade underway, one that failed and was
+/// rolled back, and one refused because the version jump is not supported.
+pub const UPGRADE_IN_PROGRESS_REASON: &str = "UpgradeInProgress";
+pub const UPGRADE_FAILED_REASON: &str = "UpgradeFailed";
+pub const UPGRADE_NOT_SUPPORTED_REASON: &str = "UpgradeNotSupported";
+
+pub const SCALING_PHASE_WAITING_FOR_POD: &str = "WaitingForPod";
+pub const SCALING_PHASE_WAITING_FOR_SYNC: &str = "WaitingForSync";
+pub const SCALING_PHASE_REMOVING_MEMBER: &str = "RemovingMember";
+pub const SCALING_PHASE_WAITING_FOR_POD_DELETION: &str = "WaitingForPodDeletion";
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct ClusterCondition {
+pub struct MembersStatus {
+    #[serde(rename = "ready", default, skip_serializing_if = "Vec::is_empty")]
+    pub ready: Vec<String>,
+
+    #[serde(rename = "unready", default, skip_serializing_if = "Vec::is_empty")]
+    pub unready: Vec<String>,
+
+    /// What each ready member reported about itself when last inspected.
+    #[serde(rename = "servers", default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<ServerStatus>,
+
+    /// When `servers` was last sampled; it is refreshed every few minutes, not on every
+    /// reconcile.
+    #[serde(rename = "serversUpdateTime", skip_serializing_if = "Option::is_none")]
+    pub servers_update_time: Option<Time>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ServerStatus {
+    #[serde(rename = "name")]
+    pub name: String,
+
+    /// leader, follower, observer or standalone.
+    #[serde(rename = "role")]
+    pub role: String,
+
+    /// Last zxid the server has applied, in hex as zookeeper logs it.
+    #[serde(rename = "lastZxid")]
+    pub last_zxid: String,
+
+    #[serde(rename = "connections")]
+    pub connections: i64,
+
+    #[serde(rename = "outstandingRequests")]
+    pub outstanding_requests: i64,
+
+    /// Request latency in milliseconds, as min/avg/max.
+    #[serde(rename = "latency")]
+    pub latency: String,
+}
+
+pub const CONDITION_TRUE: &str = "True";
+pub const CONDITION_FALSE: &str = "False";
+pub const CONDITION_UNKNOWN: &str  = "Unknown";
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ClusterCondition {
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
-    condition_type: Option<ClusterConditionType>,
+    pub condition_type: Option<ClusterConditionType>,
 
     #[serde(rename = "status", skip_serializing_if = "Option::is_none")]
-    status: Option<String>, // original type: ConditionStatus
+    pub status: Option<String>, // original type: ConditionStatus
 
     #[serde(rename = "reason", skip_serializing_if = "Option::is_none")]
-    reason: Option<String>,
+    pub reason: Option<String>,
 
     #[serde(rename = "message", skip_serializing_if = "Option::is_none")]
-    message: Option<String>,
+    pub message: Option<String>,
 
     #[serde(rename = "lastUpdateTime", skip_serializing_if = "Option::is_none")]
-    last_update_time: Option<String>,
+    pub last_update_time: Option<Time>,
 
     #[serde(rename = "lastTransitionTime", skip_serializing_if = "Option::is_none")]
-    last_transition_time: Option<String>,
+    pub last_transition_time: Option<Time>,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
-enum ClusterConditionType {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ClusterConditionType {
     #[serde(rename = "PodsReady")]
     PodsReady,
     #[serde(rename = "Upgrading")]
     Upgrading,
     #[serde(rename = "Error")]
     Error,
-}
\ No newline at end of file
+}
+
+impl ZookeeperClusterStatus {
+    /// Set a condition, creating it if missing. lastTransitionTime only moves when the
+    /// status flips, lastUpdateTime whenever status, reason or message change, so an
+    /// unchanged condition leaves the status untouched.
+    pub fn set_condition(&mut self, condition_type: ClusterConditionType, status: &str, reason: &str, message: &str) {
+        let now = Time(Utc::now());
+        let index = match self.conditions.iter().position(|c| c.condition_type.as_ref() == Some(&condition_type)) {
+            Some(index) => index,
+            None => {
+                self.conditions.push(ClusterCondition {
+                    condition_type: Some(condition_type),
+                    ..Default::default()
+                });
+                self.conditions.len() - 1
+            }
+        };
+        let condition = &mut self.conditions[index];
+        if condition.status.as_deref() != Some(status) {
+            condition.status = Some(status.to_owned());
+            condition.last_transition_time = Some(now.clone());
+            condition.last_update_time = Some(now.clone());
+        }
+        if condition.reason.as_deref() != Some(reason) || condition.message.as_deref() != Some(message) {
+            condition.reason = Some(reason.to_owned());
+            condition.message = Some(message.to_owned());
+            condition.last_update_time = Some(now);
+        }
+    }
+
+    pub fn get_condition(&self, condition_type: ClusterConditionType) -> Option<&ClusterCondition> {
+        self.conditions.iter().find(|c| c.condition_type.as_ref() == Some(&condition_type))
+    }
+
+    /// Whether the condition is present with status True.
+    pub fn is_true(&self, condition_type: ClusterConditionType) -> bool {
+        self.get_condition(condition_type)
+            .map(|c| c.status.as_deref() == Some(CONDITION_TRUE))
+            .unwrap_or(false)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::chrono::{TimeZone, Utc};
+
+    fn long_ago() -> Option<Time> {
+        Some(Time(Utc.timestamp_opt(0, 0).unwrap()))
+    }
+
+    /// Status with an Upgrading condition whose timestamps are both long past.
+    fn upgrading(status: &str, reason: &str, message: &str) -> ZookeeperClusterStatus {
+        let mut s = ZookeeperClusterStatus::default();
+        s.set_condition(ClusterConditionType::Upgrading, status, reason, message);
+        s.conditions[0].last_update_time = long_ago();
+        s.conditions[0].last_transition_time = long_ago();
+        s
+    }
+
+    #[test]
+    fn set_condition_creates_a_missing_condition() {
+        let mut s = ZookeeperClusterStatus::default();
+        s.set_condition(ClusterConditionType::Upgrading, CONDITION_TRUE, "UpgradeInProgress", "going");
+        let c = s.get_condition(ClusterConditionType::Upgrading).unwrap();
+        assert_eq!(c.status.as_deref(), Some(CONDITION_TRUE));
+        assert_eq!(c.reason.as_deref(), Some("UpgradeInProgress"));
+        assert_eq!(c.message.as_deref(), Some("going"));
+        assert!(c.last_update_time.is_some() && c.last_transition_time.is_some());
+        assert!(s.is_true(ClusterConditionType::Upgrading));
+    }
+
+    #[test]
+    fn set_condition_new_message_only_moves_the_update_time() {
+        let mut s = upgrading(CONDITION_TRUE, "UpgradeInProgress", "1 of 3 members updated");
+        s.set_condition(ClusterConditionType::Upgrading, CONDITION_TRUE, "UpgradeInProgress", "2 of 3 members updated");
+        let c = &s.conditions[0];
+        assert_eq!(c.message.as_deref(), Some("2 of 3 members updated"));
+        assert_ne!(c.last_update_time, long_ago());
+        assert_eq!(c.last_transition_time, long_ago());
+    }
+
+    #[test]
+    fn set_condition_status_flip_moves_both_times() {
+        let mut s = upgrading(CONDITION_TRUE, "UpgradeInProgress", "going");
+        s.set_condition(ClusterConditionType::Upgrading, CONDITION_FALSE, "", "");
+        let c = &s.conditions[0];
+        assert_eq!(c.status.as_deref(), Some(CONDITION_FALSE));
+        assert_ne!(c.last_update_time, long_ago());
+        assert_ne!(c.last_transition_time, long_ago());
+    }
+
+    #[test]
+    fn set_condition_unchanged_is_a_no_op() {
+        let mut s = upgrading(CONDITION_TRUE, "UpgradeInProgress", "going");
+        let before = s.clone();
+        s.set_condition(ClusterConditionType::Upgrading, CONDITION_TRUE, "UpgradeInProgress", "going");
+        assert_eq!(s, before);
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use async_trait::async_trait;
+use zookeeper::ZkError;
+use super::error::Error;
+use super::zookeeper_client_go::{CreateMode, EnsembleConfig, ZookeeperClient};
+
+
+/// In-memory stand-in for a zookeeper ensemble. Znodes carry a version that starts at 0
+/// and is bumped on every write, with the same version checks zookeeper applies.
+/// Ephemeral znodes are kept like persistent ones since there is no session to expire,
+/// and reconfigs apply instantly to an ensemble config that starts out empty.
+#[derive(Default)]
+pub struct InMemoryZookeeperClient {
+    nodes: Mutex<BTreeMap<String, (Vec<u8>, i32)>>,
+    sequences: Mutex<BTreeMap<String, i32>>,
+    config: Mutex<EnsembleConfig>,
+    kept: Mutex<BTreeSet<i32>>,
+}
+
+impl InMemoryZookeeperClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave server `id` in the config whatever later reconfigs ask for, like an
+    /// ensemble that commits a config other than the one requested.
+    pub fn keep_server(&self, id: i32) {
+        self.kept.lock().unwrap().insert(id);
+    }
+}
+
+fn check_version(expected: Option<i32>, actual: i32) -> Result<(), Error> {
+    match expected {
+        Some(v) if v != -1 && v != actual => Err(ZkError::BadVersion.into()),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl ZookeeperClient for InMemoryZookeeperClient {
+    async fn create(&self, path: &str, data: Vec<u8>, mode: CreateMode) -> Result<String, Error> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let (parent, _) = path.rsplit_once('/').ok_or(ZkError::BadArguments)?;
+        if !parent.is_empty() && !nodes.contains_key(parent) {
+            return Err(ZkError::NoNode.into());
+        }
+        let path = match mode {
+            CreateMode::PersistentSequential | CreateMode::EphemeralSequential => {
+                let mut sequences = self.sequences.lock().unwrap();
+                let sequence = sequences.entry(parent.to_owned()).or_default();
+                *sequence += 1;
+                format!("{}{:010}", path, *sequence - 1)
+            }
+            _ => path.to_owned(),
+        };
+        if nodes.contains_key(&path) {
+            return Err(ZkError::NodeExists.into());
+        }
+        nodes.insert(path.clone(), (data, 0));
+        Ok(path)
+    }
+
+    async fn update_node(&self, path: &str, data: &str, version: i32) -> Result<(), Error> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let node = nodes.get_mut(path).ok_or(ZkError::NoNode)?;
+        check_version(Some(version), node.1)?;
+        *node = (data.as_bytes().to_vec(), node.1 + 1);
+        Ok(())
+    }
+
+    async fn node_exists(&self, z_node_path: &str) -> Result<i32, Error> {
+        let nodes = self.nodes.lock().unwrap();
+        Ok(nodes.get(z_node_path).ok_or(ZkError::NoNode)?.1)
+    }
+
+    async fn get_node(&self, path: &str) -> Result<(Vec<u8>, i32), Error> {
+        let nodes = self.nodes.lock().unwrap();
+        Ok(nodes.get(path).cloned().ok_or(ZkError::NoNode)?)
+    }
+
+    async fn watch_node(&self, _path: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn get_config(&self) -> Result<EnsembleConfig, Error> {
+        Ok(self.config.lock().unwrap().clone())
+    }
+
+    async fn reconfig(&self, joining: Vec<String>, leaving: Vec<i32>, from_version: i64) -> Result<EnsembleConfig, Error> {
+        let mut config = self.config.lock().unwrap();
+        if from_version != -1 && from_version != config.version {
+            return Err(ZkError::BadVersion.into());
+        }
+        for server in joining {
+            let (key, spec) = server.split_once('=').ok_or(ZkError::BadArguments)?;
+            let id = key.strip_prefix("server.").and_then(|id| id.parse().ok()).ok_or(ZkError::BadArguments)?;
+            config.servers.insert(id, spec.to_owned());
+        }
+        let kept = self.kept.lock().unwrap();
+        for id in leaving.into_iter().filter(|id| !kept.contains(id)) {
+            config.servers.remove(&id);
+        }
+        config.version += 1;
+        config.zxid += 1;
+        Ok(config.clone())
+    }
+
+    async fn delete_node(&self, path: &str, version: Option<i32>) -> Result<(), Error> {
+        let mut nodes = self.nodes.lock().unwrap();
+        let actual = nodes.get(path).ok_or(ZkError::NoNode)?.1;
+        check_version(version, actual)?;
+        let prefix = format!("{}/", path);
+        if nodes.keys().any(|k| k.starts_with(&prefix)) {
+            return Err(ZkError::NotEmpty.into());
+        }
+        nodes.remove(path);
+        Ok(())
+    }
+
+    async fn close(&self) {}
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zookeeper_type::ZookeeperCluster;
+
+    fn cluster(replicas: i32) -> ZookeeperCluster {
+        let mut z = ZookeeperCluster::new("zk", Default::default());
+        z.spec.replicas = replicas;
+        z
+    }
+
+    #[tokio::test]
+    async fn create_node_writes_cluster_size_at_version_zero() {
+        let client = InMemoryZookeeperClient::new();
+        client.create_node(&cluster(3), "/zookeeper-operator/zk").await.unwrap();
+
+        assert_eq!(client.node_exists("/zookeeper-operator").await.unwrap(), 0);
+        let (data, version) = client.get_node("/zookeeper-operator/zk").await.unwrap();
+        assert_eq!(data, b"CLUSTER_SIZE=3");
+        assert_eq!(version, 0);
+        assert!(matches!(
+            client.create("/zookeeper-operator/zk", vec![], CreateMode::Persistent).await,
+            Err(Error::Zookeeper(ZkError::NodeExists))
+        ));
+    }
+
+    #[tokio::test]
+    async fn create_requires_the_parent() {
+        let client = InMemoryZookeeperClient::new();
+        assert!(matches!(
+            client.create("/missing/child", vec![], CreateMode::Persistent).await,
+            Err(Error::Zookeeper(ZkError::NoNode))
+        ));
+    }
+
+    #[tokio::test]
+    async fn update_node_checks_and_bumps_version() {
+        let client = InMemoryZookeeperClient::new();
+        client.create_node(&cluster(3), "/zookeeper-operator/zk").await.unwrap();
+
+        assert!(matches!(
+            client.update_node("/zookeeper-operator/zk", "CLUSTER_SIZE=5", 1).await,
+            Err(Error::Zookeeper(ZkError::BadVersion))
+        ));
+        client.update_node("/zookeeper-operator/zk", "CLUSTER_SIZE=5", 0).await.unwrap();
+        let (data, version) = client.get_node("/zookeeper-operator/zk").await.unwrap();
+        assert_eq!(data, b"CLUSTER_SIZE=5");
+        assert_eq!(version, 1);
+    }
+
+    #[tokio::test]
+    async fn delete_node_refuses_parents_with_children() {
+        let client = InMemoryZookeeperClient::new();
+        client.create_node(&cluster(3), "/zookeeper-operator/zk").await.unwrap();
+
+        assert!(matches!(
+            client.delete_node("/zookeeper-operator", None).await,
+            Err(Error::Zookeeper(ZkError::NotEmpty))
+        ));
+        client.delete_node("/zookeeper-operator/zk", Some(0)).await.unwrap();
+        assert!(matches!(
+            client.node_exists("/zookeeper-operator/zk").await,
+            Err(Error::Zookeeper(ZkError::NoNode))
+        ));
+    }
+}
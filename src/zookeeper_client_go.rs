@@ -1,75 +1,570 @@
-use std::{fmt, time::Duration};
-use std::str::FromStr;
-use std::convert::TryInto;
-use std::collections::HashMap;
-use zookeeper::{CreateMode, Watcher, WatchedEvent, ZkResult, ZooKeeper, ZkError};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
+use futures::channel::mpsc::UnboundedSender;
+pub use zookeeper::CreateMode;
+use zookeeper::{Watcher, WatchedEvent, WatchedEventType, ZooKeeper, ZkError, ZkState};
 use zookeeper as zk;
 use super::zookeeper_type::ZookeeperCluster;
+use super::error::Error;
+use super::zookeeper_reconfig;
 
 
+/// Operations the reconciler needs from a zookeeper ensemble. Implemented by
+/// `DefaultZookeeperClient` for real ensembles and by an in-memory fake in tests.
+#[async_trait]
+pub trait ZookeeperClient: Send + Sync {
+    /// Create a single znode whose parent must already exist, returning the path created
+    /// (which differs from `path` for sequential modes).
+    async fn create(&self, path: &str, data: Vec<u8>, mode: CreateMode) -> Result<String, Error>;
+
+    /// Create `path` with `data` and `mode`, first creating every missing ancestor as an
+    /// empty persistent znode. Znodes that already exist are left untouched, so calling
+    /// this again is a no-op.
+    async fn ensure_path(&self, path: &str, data: Vec<u8>, mode: CreateMode) -> Result<String, Error> {
+        let segments = split_path(path)?;
+        if segments.is_empty() {
+            return Ok(path.to_owned());
+        }
+        let mut current = String::new();
+        for (i, segment) in segments.iter().enumerate() {
+            current.push('/');
+            current.push_str(segment);
+            let (data, mode) = if i + 1 == segments.len() {
+                (data.clone(), mode)
+            } else {
+                (vec![], CreateMode::Persistent)
+            };
+            match self.create(&current, data, mode).await {
+                Ok(created) if i + 1 == segments.len() => return Ok(created),
+                Ok(_) | Err(Error::Zookeeper(ZkError::NodeExists)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(current)
+    }
+
+    /// Create `z_node_path` and any missing parents, storing the cluster size in it.
+    async fn create_node(&self, zoo: &ZookeeperCluster, z_node_path: &str) -> Result<(), Error> {
+        let data = format!("CLUSTER_SIZE={}", zoo.spec.replicas);
+        self.ensure_path(z_node_path, data.into_bytes(), CreateMode::Persistent).await?;
+        Ok(())
+    }
+    /// Overwrite the data of `path` if its version still matches.
+    async fn update_node(&self, path: &str, data: &str, version: i32) -> Result<(), Error>;
+    /// Version of `z_node_path`, or `ZkError::NoNode` if it does not exist.
+    #[allow(dead_code)]
+    async fn node_exists(&self, z_node_path: &str) -> Result<i32, Error>;
+    /// Data and version of `path`.
+    async fn get_node(&self, path: &str) -> Result<(Vec<u8>, i32), Error>;
+    /// Leave a one-shot watch on `path` that fires when it is created, changed or deleted.
+    async fn watch_node(&self, path: &str) -> Result<(), Error>;
+    /// Ensemble membership as last committed.
+    async fn get_config(&self) -> Result<EnsembleConfig, Error>;
+    /// Incrementally add the `joining` servers (`server.<id>=<spec>` lines) and remove the
+    /// `leaving` server ids, failing with BadVersion unless the config is still at
+    /// `from_version`. Returns the config the ensemble committed.
+    async fn reconfig(&self, joining: Vec<String>, leaving: Vec<i32>, from_version: i64) -> Result<EnsembleConfig, Error>;
+    /// Delete `path`, checking its version unless `version` is None.
+    async fn delete_node(&self, path: &str, version: Option<i32>) -> Result<(), Error>;
+    async fn close(&self);
+}
+
+
+/// Ensemble membership as stored in zookeeper's `/zookeeper/config` znode.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnsembleConfig {
+    /// Spec of every server by id, e.g. `host:2888:3888:participant;0.0.0.0:2181`.
+    pub servers: BTreeMap<i32, String>,
+    /// Version an incremental reconfig has to start from.
+    pub version: i64,
+    /// Zxid of the transaction that committed this config.
+    pub zxid: i64,
+}
+
+pub const CONFIG_NODE: &str = "/zookeeper/config";
+
+impl EnsembleConfig {
+    /// Parse the `server.<id>=<spec>` and hex `version=` lines of a dynamic config.
+    pub fn parse(data: &[u8], zxid: i64) -> Self {
+        let mut config = Self { zxid, ..Default::default() };
+        for line in String::from_utf8_lossy(data).lines() {
+            match line.trim().split_once('=') {
+                Some(("version", version)) => config.version = i64::from_str_radix(version, 16).unwrap_or(-1),
+                Some((key, spec)) => {
+                    if let Some(id) = key.strip_prefix("server.").and_then(|id| id.parse().ok()) {
+                        config.servers.insert(id, spec.to_owned());
+                    }
+                }
+                None => {}
+            }
+        }
+        config
+    }
+
+    /// Whether server `id` is a voting member rather than an observer or absent.
+    pub fn is_participant(&self, id: i32) -> bool {
+        self.servers
+            .get(&id)
+            .map(|spec| !spec.split(';').next().unwrap_or_default().ends_with(":observer"))
+            .unwrap_or(false)
+    }
+}
+
+
+/// Split an absolute znode path into its segments, rejecting relative paths and empty
+/// segments. The root splits into no segments.
+fn split_path(path: &str) -> Result<Vec<&str>, Error> {
+    let rest = path.strip_prefix('/').ok_or(ZkError::BadArguments)?;
+    if rest.is_empty() {
+        return Ok(vec![]);
+    }
+    let segments = rest.split('/').collect::<Vec<_>>();
+    if segments.iter().any(|s| s.is_empty()) {
+        return Err(ZkError::BadArguments.into());
+    }
+    Ok(segments)
+}
+
+
+/// How long a single zookeeper call may take before the reconcile gives up on it.
+pub const DEFAULT_OPERATION_TIMEOUT: Duration = Duration::from_secs(10);
+const SESSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+/// Username and password for zookeeper's digest authentication scheme.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DigestCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl DigestCredentials {
+    /// `username:base64(sha1(username:password))`, the form servers take these
+    /// credentials in as `zookeeper.DigestAuthenticationProvider.superDigest`.
+    pub fn super_digest(&self) -> String {
+        let hash = openssl::sha::sha1(format!("{}:{}", self.username, self.password).as_bytes());
+        format!("{}:{}", self.username, openssl::base64::encode_block(&hash))
+    }
+}
+
+impl std::fmt::Debug for DigestCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DigestCredentials").field("username", &self.username).finish_non_exhaustive()
+    }
+}
+
+
+/// Async facade over the blocking `zookeeper` crate. Every call runs on tokio's blocking
+/// pool and is bounded by `timeout`, so a slow ensemble cannot stall the worker threads.
 pub struct DefaultZookeeperClient {
-    conn: ZooKeeper,
+    conn: Arc<ZooKeeper>,
+    // reconfig dials its own session, see zookeeper_reconfig
+    address: String,
+    timeout: Duration,
+    // set once the session has ended; the connection is useless afterwards
+    closed: Arc<AtomicBool>,
+    credentials: Option<DigestCredentials>,
+}
+
+
+/// What happened on an ensemble that warrants another look at its cluster.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ZookeeperEventKind {
+    /// The session lost its server and is trying to reconnect.
+    Disconnected,
+    /// The session got a server back after being disconnected.
+    Connected,
+    /// The session ended for good; the next reconcile dials a new one.
+    Expired,
+    /// A watched znode was created, changed or deleted.
+    NodeChanged(String),
+}
+
+/// An ensemble event, tagged with the namespace/name of the cluster it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZookeeperEvent {
+    pub cluster: String,
+    pub kind: ZookeeperEventKind,
+}
+
+/// Reports a client's session changes and watch notifications for one cluster. Doubles as
+/// the connection's default watcher, which is where znode watches set by the client fire.
+#[derive(Clone)]
+pub struct EventForwarder {
+    cluster: String,
+    sender: UnboundedSender<ZookeeperEvent>,
+}
+
+impl EventForwarder {
+    pub fn new(cluster: String, sender: UnboundedSender<ZookeeperEvent>) -> Self {
+        Self { cluster, sender }
+    }
+
+    fn send(&self, kind: ZookeeperEventKind) {
+        // the receiver only goes away when the controller shuts down
+        let _ = self.sender.unbounded_send(ZookeeperEvent { cluster: self.cluster.clone(), kind });
+    }
 }
 
-struct MyWatcher; // This is a custom watcher that must be implemented if using zk-rust. Still not sure how to use it.
-impl Watcher for MyWatcher {
+impl Watcher for EventForwarder {
     fn handle(&self, event: WatchedEvent) {
-        println!("Node event: {:?}", event);
+        let changed = matches!(
+            event.event_type,
+            WatchedEventType::NodeCreated | WatchedEventType::NodeDeleted | WatchedEventType::NodeDataChanged
+        );
+        if let (true, Some(path)) = (changed, event.path) {
+            self.send(ZookeeperEventKind::NodeChanged(path));
+        }
     }
 }
 
+/// ACL for znodes created with `credentials`: only the authenticated operator may change
+/// them while anyone may read them, or anyone may do anything when the session is
+/// unauthenticated.
+fn acl_for(credentials: Option<&DigestCredentials>) -> Vec<zk::Acl> {
+    if credentials.is_some() {
+        // the image's start and teardown scripts read CLUSTER_SIZE without credentials
+        [zk::Acl::creator_all().as_slice(), zk::Acl::read_unsafe().as_slice()].concat()
+    } else {
+        zk::Acl::open_unsafe().clone()
+    }
+}
 
-impl DefaultZookeeperClient {
-    pub fn new(zk_uri: &str ) -> Self {
-        let conn = ZooKeeper::connect(zk_uri, Duration::from_secs(5), MyWatcher).unwrap();
-        Self {
-            conn,
+/// Report a session state change through `events`, once per transition: `disconnected`
+/// tracks whether the session is between servers, and `closed` whether it has ended.
+fn report_session_state(state: ZkState, closed: &AtomicBool, disconnected: &AtomicBool, events: &EventForwarder) {
+    match state {
+        ZkState::Closed | ZkState::AuthFailed => {
+            // `close` marks the client first, so only unexpected ends are reported
+            if !closed.swap(true, Ordering::SeqCst) {
+                events.send(ZookeeperEventKind::Expired);
+            }
+        }
+        ZkState::Connected | ZkState::ConnectedReadOnly => {
+            if disconnected.swap(false, Ordering::SeqCst) {
+                events.send(ZookeeperEventKind::Connected);
+            }
+        }
+        _ => {
+            if !disconnected.swap(true, Ordering::SeqCst) {
+                events.send(ZookeeperEventKind::Disconnected);
+            }
         }
     }
-    pub fn connect(&mut self, zk_uri: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let conn = ZooKeeper::connect(zk_uri, Duration::from_secs(5), MyWatcher)?;
-        self.conn = conn;
-        Ok(())
-    }
+}
+
 
-    pub fn create_node(&self, zoo: &ZookeeperCluster, z_node_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-        // @TODO: Not sure whether flags=0 in zk-Go means CreateMode::Persistent in zk-rust.
-        let paths = z_node_path.split('/').filter(|&p| !p.is_empty()).collect::<Vec<_>>();
-        let path_length = paths.len();
-        let mut parent_path = String::new();
-        for i in 1..(path_length - 1) {
-            parent_path.push('/');
-            parent_path.push_str(paths[i]);
-            match self.conn.create(&parent_path, vec![], zk::Acl::open_unsafe().clone(),CreateMode::Persistent) {
-                Ok(_) => {},
-                Err(e) if e == zk::ZkError::NodeExists => {}, // Ignore if node already exists.
-                Err(e) => return Err(Box::new(e))
+impl DefaultZookeeperClient {
+    /// Connect to `zk_uri`, authenticating the session with `credentials` when given and
+    /// reporting session changes and watch notifications to `events`.
+    pub async fn new(zk_uri: &str, credentials: Option<DigestCredentials>, events: EventForwarder) -> Result<Self, Error> {
+        let client = Self {
+            conn: Self::dial(zk_uri.to_owned(), DEFAULT_OPERATION_TIMEOUT, events.clone()).await?,
+            address: zk_uri.to_owned(),
+            timeout: DEFAULT_OPERATION_TIMEOUT,
+            closed: Arc::new(AtomicBool::new(false)),
+            credentials,
+        };
+        client.watch_session(events);
+        if let Some(credentials) = client.credentials.clone() {
+            let auth = format!("{}:{}", credentials.username, credentials.password);
+            if let Err(e) = client.run(move |conn| Ok(conn.add_auth("digest", auth.into_bytes())?)).await {
+                client.close().await;
+                return Err(e);
             }
         }
-        let data = format!("CLUSTER_SIZE={}", zoo.spec.replicas);
-        let child_node = format!("{}{}", parent_path, paths[path_length-1]);
-        self.conn.create(&child_node, data.as_bytes().to_vec(), zk::Acl::open_unsafe().clone(),CreateMode::Persistent)?;
-        Ok(())
+        Ok(client)
     }
 
-    pub fn update_node(&self, path: &str, data: &str, version: i32) -> Result<(), Box<dyn std::error::Error>> {
-        let version_opt = Option::from(version);
-        self.conn.set_data(path, data.as_bytes().to_vec(), version_opt)?;
-        Ok(())
+    /// The credentials this session authenticated with.
+    pub fn credentials(&self) -> Option<&DigestCredentials> {
+        self.credentials.as_ref()
     }
 
-    pub fn node_exists(&self, z_node_path: &str) -> Result<i32, Box<dyn std::error::Error>> {
-        match self.conn.exists(z_node_path, false) {
+    /// ACL for znodes this client creates, see `acl_for`.
+    fn acl(&self) -> Vec<zk::Acl> {
+        acl_for(self.credentials.as_ref())
+    }
+
+    /// Whether the session has expired or been closed, so a new connection is needed.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Track the session state, reporting each transition between connected, disconnected
+    /// and ended once.
+    fn watch_session(&self, events: EventForwarder) {
+        let closed = self.closed.clone();
+        let disconnected = AtomicBool::new(false);
+        self.conn.add_listener(move |state| report_session_state(state, &closed, &disconnected, &events));
+    }
+
+    async fn dial(zk_uri: String, timeout: Duration, watcher: EventForwarder) -> Result<Arc<ZooKeeper>, Error> {
+        let conn = blocking_with_timeout(timeout, move || {
+            Ok(ZooKeeper::connect(&zk_uri, SESSION_TIMEOUT, watcher)?)
+        }).await?;
+        Ok(Arc::new(conn))
+    }
+
+    /// Run a blocking call against the connection on the blocking pool.
+    async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ZooKeeper) -> Result<T, Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        blocking_with_timeout(self.timeout, move || f(&conn)).await
+    }
+}
+
+#[async_trait]
+impl ZookeeperClient for DefaultZookeeperClient {
+    async fn create(&self, path: &str, data: Vec<u8>, mode: CreateMode) -> Result<String, Error> {
+        let (path, acl) = (path.to_owned(), self.acl());
+        self.run(move |conn| Ok(conn.create(&path, data, acl, mode)?)).await
+    }
+
+    async fn update_node(&self, path: &str, data: &str, version: i32) -> Result<(), Error> {
+        let (path, data) = (path.to_owned(), data.to_owned());
+        self.run(move |conn| {
+            conn.set_data(&path, data.into_bytes(), Some(version))?;
+            Ok(())
+        }).await
+    }
+
+    async fn node_exists(&self, z_node_path: &str) -> Result<i32, Error> {
+        let z_node_path = z_node_path.to_owned();
+        self.run(move |conn| match conn.exists(&z_node_path, false) {
             Ok(Some(stat)) => Ok(stat.version),
-            Ok(None) => Err(Box::new(ZkError::NoNode)),
-            Err(e) => Err(Box::new(e)),
-        }
+            Ok(None) => Err(ZkError::NoNode.into()),
+            Err(e) => Err(e.into()),
+        }).await
+    }
+
+    async fn get_node(&self, path: &str) -> Result<(Vec<u8>, i32), Error> {
+        let path = path.to_owned();
+        self.run(move |conn| {
+            let (data, stat) = conn.get_data(&path, false)?;
+            Ok((data, stat.version))
+        }).await
+    }
+
+    async fn delete_node(&self, path: &str, version: Option<i32>) -> Result<(), Error> {
+        let path = path.to_owned();
+        self.run(move |conn| Ok(conn.delete(&path, version)?)).await
+    }
+
+    async fn watch_node(&self, path: &str) -> Result<(), Error> {
+        let path = path.to_owned();
+        self.run(move |conn| {
+            conn.exists(&path, true)?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_config(&self) -> Result<EnsembleConfig, Error> {
+        self.run(|conn| {
+            let (data, stat) = conn.get_data(CONFIG_NODE, false)?;
+            Ok(EnsembleConfig::parse(&data, stat.mzxid))
+        }).await
+    }
+
+    /// Needs a session allowed to write `/zookeeper/config`, which with ACLs enforced only a
+    /// superuser is: the servers take the operator's credentials as their superDigest.
+    async fn reconfig(&self, joining: Vec<String>, leaving: Vec<i32>, from_version: i64) -> Result<EnsembleConfig, Error> {
+        let (address, timeout) = (self.address.clone(), self.timeout);
+        let auth = self.credentials.as_ref().map(|c| format!("{}:{}", c.username, c.password));
+        let joining = Some(joining.join(",")).filter(|j| !j.is_empty());
+        let leaving = Some(leaving.iter().map(i32::to_string).collect::<Vec<_>>().join(",")).filter(|l| !l.is_empty());
+        blocking_with_timeout(timeout, move || {
+            let auth = auth.as_ref().map(|a| ("digest", a.as_bytes()));
+            let (data, zxid) = zookeeper_reconfig::reconfig(
+                &address, auth, joining.as_deref(), leaving.as_deref(), from_version, timeout,
+            )?;
+            Ok(EnsembleConfig::parse(&data, zxid))
+        }).await
     }
 
-    pub fn close(&self) {
-        match self.conn.close(){
+    async fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        match self.run(|conn| Ok(conn.close()?)).await {
             Ok(_) => {},
             Err(e) => println!("Error while closing connection: {:?}", e),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Run `f` on the blocking pool, failing with `Error::ZookeeperTimeout` if it does not
+/// return within `timeout`. The blocking call itself keeps running until the zookeeper
+/// session gives up on it.
+async fn blocking_with_timeout<T, F>(timeout: Duration, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    match tokio::time::timeout(timeout, tokio::task::spawn_blocking(f)).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(join_error)) => std::panic::resume_unwind(join_error.into_panic()),
+        Err(_) => Err(Error::ZookeeperTimeout(timeout)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zookeeper_client_fake::InMemoryZookeeperClient;
+    use futures::channel::mpsc::UnboundedReceiver;
+
+    fn forwarder() -> (EventForwarder, UnboundedReceiver<ZookeeperEvent>) {
+        let (sender, receiver) = futures::channel::mpsc::unbounded();
+        (EventForwarder::new("default/zk".to_owned(), sender), receiver)
+    }
+
+    fn received(receiver: &mut UnboundedReceiver<ZookeeperEvent>) -> Vec<ZookeeperEventKind> {
+        std::iter::from_fn(|| receiver.try_next().ok().flatten()).map(|event| event.kind).collect()
+    }
+
+    #[test]
+    fn super_digest_matches_zookeeper() {
+        // as printed by org.apache.zookeeper.server.auth.DigestAuthenticationProvider
+        let credentials = DigestCredentials { username: "super".to_owned(), password: "admin".to_owned() };
+        assert_eq!(credentials.super_digest(), "super:xQJmxLMiHGwaqBvst5y6rkB6HQs=");
+    }
+
+    #[test]
+    fn acl_follows_authentication() {
+        assert_eq!(acl_for(None), vec![zk::Acl::new(zk::Permission::ALL, "world", "anyone")]);
+        let credentials = DigestCredentials { username: "operator".to_owned(), password: "secret".to_owned() };
+        assert_eq!(acl_for(Some(&credentials)), vec![
+            zk::Acl::new(zk::Permission::ALL, "auth", ""),
+            zk::Acl::new(zk::Permission::READ, "world", "anyone"),
+        ]);
+    }
+
+    #[test]
+    fn forwards_znode_changes() {
+        let (events, mut receiver) = forwarder();
+        let event = |event_type, path: Option<&str>| WatchedEvent {
+            event_type,
+            keeper_state: zk::KeeperState::SyncConnected,
+            path: path.map(str::to_owned),
+        };
+        events.handle(event(WatchedEventType::NodeDataChanged, Some("/zookeeper-operator/zk")));
+        events.handle(event(WatchedEventType::NodeChildrenChanged, Some("/zookeeper-operator")));
+        events.handle(event(WatchedEventType::None, None));
+        events.handle(event(WatchedEventType::NodeDeleted, Some("/zookeeper-operator/zk")));
+
+        let sent = receiver.try_next().unwrap().unwrap();
+        assert_eq!(sent.cluster, "default/zk");
+        assert_eq!(sent.kind, ZookeeperEventKind::NodeChanged("/zookeeper-operator/zk".to_owned()));
+        assert_eq!(received(&mut receiver), vec![ZookeeperEventKind::NodeChanged("/zookeeper-operator/zk".to_owned())]);
+    }
+
+    #[test]
+    fn reports_each_session_transition_once() {
+        let (events, mut receiver) = forwarder();
+        let (closed, disconnected) = (AtomicBool::new(false), AtomicBool::new(false));
+        for state in [
+            ZkState::Connected,
+            ZkState::Connecting,
+            ZkState::Connecting,
+            ZkState::Connected,
+            ZkState::ConnectedReadOnly,
+            ZkState::Closed,
+            ZkState::AuthFailed,
+        ] {
+            report_session_state(state, &closed, &disconnected, &events);
+        }
+        assert_eq!(received(&mut receiver), vec![
+            ZookeeperEventKind::Disconnected,
+            ZookeeperEventKind::Connected,
+            ZookeeperEventKind::Expired,
+        ]);
+
+        // a session closed on purpose goes quietly
+        let (closed, disconnected) = (AtomicBool::new(true), AtomicBool::new(false));
+        report_session_state(ZkState::Closed, &closed, &disconnected, &events);
+        assert_eq!(received(&mut receiver), vec![]);
+    }
+
+    #[tokio::test]
+    async fn ensure_path_creates_every_ancestor() {
+        let client = InMemoryZookeeperClient::new();
+        let created = client.ensure_path("/a/b/c", b"payload".to_vec(), CreateMode::Persistent).await.unwrap();
+
+        assert_eq!(created, "/a/b/c");
+        assert_eq!(client.get_node("/a").await.unwrap(), (vec![], 0));
+        assert_eq!(client.get_node("/a/b").await.unwrap(), (vec![], 0));
+        assert_eq!(client.get_node("/a/b/c").await.unwrap(), (b"payload".to_vec(), 0));
+    }
+
+    #[tokio::test]
+    async fn ensure_path_is_idempotent() {
+        let client = InMemoryZookeeperClient::new();
+        client.ensure_path("/a/b", b"first".to_vec(), CreateMode::Persistent).await.unwrap();
+        client.ensure_path("/a/b", b"second".to_vec(), CreateMode::Persistent).await.unwrap();
+        client.ensure_path("/a/b/c", vec![], CreateMode::Persistent).await.unwrap();
+
+        assert_eq!(client.get_node("/a/b").await.unwrap(), (b"first".to_vec(), 0));
+    }
+
+    #[tokio::test]
+    async fn ensure_path_handles_single_segment_and_root() {
+        let client = InMemoryZookeeperClient::new();
+        client.ensure_path("/zookeeper-operator", vec![], CreateMode::Persistent).await.unwrap();
+        assert_eq!(client.node_exists("/zookeeper-operator").await.unwrap(), 0);
+        assert_eq!(client.ensure_path("/", vec![], CreateMode::Persistent).await.unwrap(), "/");
+    }
+
+    #[tokio::test]
+    async fn ensure_path_passes_create_mode_to_the_leaf_only() {
+        let client = InMemoryZookeeperClient::new();
+        let first = client.ensure_path("/locks/lock-", vec![], CreateMode::PersistentSequential).await.unwrap();
+        let second = client.ensure_path("/locks/lock-", vec![], CreateMode::PersistentSequential).await.unwrap();
+
+        assert_eq!(first, "/locks/lock-0000000000");
+        assert_eq!(second, "/locks/lock-0000000001");
+        assert!(client.node_exists("/locks").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn ensure_path_rejects_malformed_paths() {
+        let client = InMemoryZookeeperClient::new();
+        for path in ["relative/path", "/a//b", "/a/", ""] {
+            assert!(matches!(
+                client.ensure_path(path, vec![], CreateMode::Persistent).await,
+                Err(Error::Zookeeper(ZkError::BadArguments))
+            ), "{:?} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn ensemble_config_parses_servers_and_version() {
+        let data = b"server.1=zk-0.zk-headless:2888:3888:participant;0.0.0.0:2181\n\
+                     server.2=zk-1.zk-headless:2888:3888:observer;0.0.0.0:2181\n\
+                     version=10000002a";
+        let config = EnsembleConfig::parse(data, 7);
+
+        assert_eq!(config.servers.len(), 2);
+        assert_eq!(config.version, 0x10000002a);
+        assert_eq!(config.zxid, 7);
+        assert!(config.is_participant(1));
+        assert!(!config.is_participant(2));
+        assert!(!config.is_participant(3));
+    }
+
+    #[tokio::test]
+    async fn create_node_stores_cluster_size() {
+        let client = InMemoryZookeeperClient::new();
+        let mut zoo = ZookeeperCluster::new("zk", Default::default());
+        zoo.spec.replicas = 5;
+        client.create_node(&zoo, "/zookeeper-operator/zk").await.unwrap();
+
+        let (data, _) = client.get_node("/zookeeper-operator/zk").await.unwrap();
+        assert_eq!(data, b"CLUSTER_SIZE=5");
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use futures::channel::mpsc::UnboundedSender;
+use kube::ResourceExt;
+use tracing::*;
+use super::error::Error;
+use super::zookeeper_client_go::{DefaultZookeeperClient, DigestCredentials, EventForwarder, ZookeeperClient, ZookeeperEvent};
+use super::zookeeper_type::ZookeeperCluster;
+
+
+/// One zookeeper connection per ZookeeperCluster, keyed by namespace/name and dialed
+/// through the cluster's own client Service the first time it is needed. Every connection
+/// reports its ensemble events on the pool's `events` channel.
+pub struct ZookeeperClientPool {
+    clients: Mutex<HashMap<String, Arc<DefaultZookeeperClient>>>,
+    events: UnboundedSender<ZookeeperEvent>,
+}
+
+fn pool_key(namespace: &str, name: &str) -> String {
+    format!("{}/{}", namespace, name)
+}
+
+impl ZookeeperClientPool {
+    pub fn new(events: UnboundedSender<ZookeeperEvent>) -> Self {
+        Self { clients: Mutex::default(), events }
+    }
+
+    /// Connection to the cluster's ensemble authenticated with `credentials`, reconnecting
+    /// if the previous session expired or the credentials have changed since.
+    pub async fn get(&self, z: &ZookeeperCluster, credentials: Option<DigestCredentials>) -> Result<Arc<dyn ZookeeperClient>, Error> {
+        let key = pool_key(&z.namespace().unwrap_or_default(), &z.name_any());
+        if let Some(client) = self.clients.lock().unwrap().get(&key) {
+            if !client.is_closed() && client.credentials() == credentials.as_ref() {
+                return Ok(client.clone());
+            }
+        }
+
+        let address = z.get_client_service_address();
+        info!("Connecting to zookeeper cluster {} at {}", key, address);
+        let events = EventForwarder::new(key.clone(), self.events.clone());
+        let client = Arc::new(DefaultZookeeperClient::new(&address, credentials, events).await?);
+        let stale = self.clients.lock().unwrap().insert(key, client.clone());
+        if let Some(stale) = stale {
+            stale.close().await;
+        }
+        Ok(client)
+    }
+
+    /// Close and forget the connection of a cluster that no longer exists.
+    pub async fn remove(&self, namespace: &str, name: &str) {
+        let client = self.clients.lock().unwrap().remove(&pool_key(namespace, name));
+        if let Some(client) = client {
+            info!("Closing zookeeper connection for {}/{}", namespace, name);
+            client.close().await;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::*;
+use super::error::Error;
+use super::zookeeper_type::ZookeeperCluster;
+
+
+/// How long a four letter word or AdminServer exchange with a single server may take.
+pub const INSPECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const MODE_LEADER: &str = "leader";
+pub const MODE_FOLLOWER: &str = "follower";
+pub const MODE_OBSERVER: &str = "observer";
+
+
+/// What a server reports about itself through `srvr` or the AdminServer.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// leader, follower, observer or standalone.
+    pub mode: String,
+    /// Last zxid the server has applied.
+    pub zxid: i64,
+    /// Requests queued on the server and not yet processed.
+    pub outstanding_requests: i64,
+    /// Client connections open on the server.
+    pub connections: i64,
+    /// Request latency in milliseconds, as min/avg/max.
+    pub latency: String,
+}
+
+impl ServerStats {
+    /// Whether the server has finished syncing with a leader and serves as part of the quorum.
+    pub fn is_synced(&self) -> bool {
+        [MODE_LEADER, MODE_FOLLOWER, MODE_OBSERVER].contains(&self.mode.as_str())
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.mode == MODE_LEADER
+    }
+}
+
+/// Inspect member `ordinal` of the ensemble through `srvr`, falling back to the
+/// AdminServer when four letter words are refused or unanswered.
+pub async fn inspect_member(z: &ZookeeperCluster, ordinal: i32) -> Result<ServerStats, Error> {
+    let host = z.get_member_address(ordinal);
+    let srvr_error = match server_stats(&format!("{}:{}", host, z.get_port("client", 2181))).await {
+        Ok(stats) => return Ok(stats),
+        Err(e) => e,
+    };
+    debug!("Falling back to the AdminServer of {}: {}", host, srvr_error);
+    admin_server_stats(&format!("{}:{}", host, z.get_port("admin-server", 8080)))
+        .await
+        .map_err(|_| srvr_error)
+}
+
+/// Send a four letter word command to the server at `address` and return its reply.
+pub async fn four_letter_word(address: &str, command: &str) -> Result<String, Error> {
+    exchange(address, command.as_bytes(), command).await
+}
+
+/// Mode, zxid, load and latency of the server at `address`, from `srvr`, which reports
+/// the same counters as `mntr` but also the zxid.
+pub async fn server_stats(address: &str) -> Result<ServerStats, Error> {
+    let reply = four_letter_word(address, "srvr").await?;
+    // e.g. "This ZooKeeper instance is not currently serving requests"
+    parse_srvr(&reply).ok_or_else(|| Error::Inspection(address.to_owned(), reply.trim().to_owned()))
+}
+
+/// Parse a `srvr` reply, None if it reports no mode.
+fn parse_srvr(reply: &str) -> Option<ServerStats> {
+    let mut stats = ServerStats::default();
+    for line in reply.lines() {
+        match line.split_once(": ") {
+            Some(("Mode", mode)) => stats.mode = mode.trim().to_owned(),
+            Some(("Zxid", zxid)) => {
+                stats.zxid = i64::from_str_radix(zxid.trim().trim_start_matches("0x"), 16).unwrap_or(-1);
+            }
+            Some(("Outstanding", outstanding)) => stats.outstanding_requests = outstanding.trim().parse().unwrap_or(0),
+            Some(("Connections", connections)) => stats.connections = connections.trim().parse().unwrap_or(0),
+            Some(("Latency min/avg/max", latency)) => stats.latency = latency.trim().to_owned(),
+            _ => {}
+        }
+    }
+    (!stats.mode.is_empty()).then_some(stats)
+}
+
+#[derive(Deserialize)]
+struct AdminServerStatsReply {
+    server_stats: AdminServerStats,
+}
+
+#[derive(Deserialize)]
+struct AdminServerStats {
+    server_state: String,
+    last_processed_zxid: i64,
+    outstanding_requests: i64,
+    num_alive_client_connections: i64,
+    min_latency: i64,
+    avg_latency: f64,
+    max_latency: i64,
+}
+
+/// Mode, zxid, load and latency of the server whose AdminServer listens on
+/// `address`, from its `server_stats` command.
+pub async fn admin_server_stats(address: &str) -> Result<ServerStats, Error> {
+    let stats = admin_command::<AdminServerStatsReply>(address, "server_stats").await?.server_stats;
+    Ok(ServerStats {
+        mode: stats.server_state,
+        zxid: stats.last_processed_zxid,
+        outstanding_requests: stats.outstanding_requests,
+        connections: stats.num_alive_client_connections,
+        latency: format!("{}/{}/{}", stats.min_latency, stats.avg_latency, stats.max_latency),
+    })
+}
+
+/// Run an AdminServer command over plain HTTP and decode its JSON reply.
+async fn admin_command<T: serde::de::DeserializeOwned>(address: &str, command: &str) -> Result<T, Error> {
+    // HTTP/1.0 keeps the reply unchunked and the connection closing after it
+    let request = format!("GET /commands/{} HTTP/1.0\r\nHost: {}\r\n\r\n", command, address);
+    let reply = exchange(address, request.as_bytes(), command).await?;
+    let (head, body) = reply.split_once("\r\n\r\n").unwrap_or((reply.as_str(), ""));
+    let status_line = head.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(Error::Inspection(address.to_owned(), format!("{} answered {:?}", command, status_line)));
+    }
+    serde_json::from_str(body).map_err(|e| Error::Inspection(address.to_owned(), format!("{} reply: {}", command, e)))
+}
+
+/// Write `request` to `address` and read until the server closes the connection.
+async fn exchange(address: &str, request: &[u8], command: &str) -> Result<String, Error> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(address).await?;
+        stream.write_all(request).await?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await?;
+        Ok::<_, std::io::Error>(reply)
+    };
+    match tokio::time::timeout(INSPECTION_TIMEOUT, exchange).await {
+        Ok(Ok(reply)) => Ok(reply),
+        Ok(Err(e)) => Err(Error::Inspection(address.to_owned(), e.to_string())),
+        Err(_) => Err(Error::Inspection(address.to_owned(), format!("{} timed out", command))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_srvr_replies() {
+        let reply = "Zookeeper version: 3.6.3--6401e4ad2087061bc6b9f80dec2d69f2e3c8660a, built on 04/08/2021 16:35 GMT\n\
+            Latency min/avg/max: 0/0.4/12\n\
+            Received: 120\n\
+            Sent: 119\n\
+            Connections: 3\n\
+            Outstanding: 2\n\
+            Zxid: 0x20000001a\n\
+            Mode: leader\n\
+            Node count: 7\n";
+        let stats = parse_srvr(reply).unwrap();
+        assert_eq!(stats, ServerStats {
+            mode: MODE_LEADER.to_owned(),
+            zxid: 0x20000001a,
+            outstanding_requests: 2,
+            connections: 3,
+            latency: "0/0.4/12".to_owned(),
+        });
+        assert!(stats.is_leader() && stats.is_synced());
+        assert_eq!(parse_srvr("This ZooKeeper instance is not currently serving requests\n"), None);
+    }
+}
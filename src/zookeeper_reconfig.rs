@@ -0,0 +1,323 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use tracing::*;
+use zookeeper::ZkError;
+
+
+// Just enough of the zookeeper wire protocol to issue a reconfig, which the `zookeeper`
+// crate has no call for. Every reconfig runs in its own short-lived session.
+
+const OP_RECONFIG: i32 = 16;
+const OP_AUTH: i32 = 100;
+const OP_CLOSE_SESSION: i32 = -11;
+const AUTH_XID: i32 = -4;
+const NOTIFICATION_XID: i32 = -1;
+const PING_XID: i32 = -2;
+
+
+/// Incrementally reconfigure the ensemble behind `address`: add the `joining` server specs
+/// and remove the `leaving` server ids (both comma separated), refusing with BadVersion if
+/// the config is no longer at `from_config`. `auth` is a scheme and credentials to add to
+/// the session first. Returns the new config and the zxid that committed it.
+pub fn reconfig(
+    address: &str,
+    auth: Option<(&str, &[u8])>,
+    joining: Option<&str>,
+    leaving: Option<&str>,
+    from_config: i64,
+    timeout: Duration,
+) -> Result<(Vec<u8>, i64), ZkError> {
+    let mut session = Session::connect(address, timeout).map_err(connection_loss)?;
+    let result = session.reconfig(auth, joining, leaving, from_config);
+    // the server drops the session on its own once it times out
+    let _ = session.call(2, OP_CLOSE_SESSION, &[]);
+    result
+}
+
+fn connection_loss(e: io::Error) -> ZkError {
+    warn!("zookeeper reconfig connection failed: {}", e);
+    ZkError::ConnectionLoss
+}
+
+struct Session<S = TcpStream> {
+    stream: S,
+}
+
+impl Session {
+    fn connect(address: &str, timeout: Duration) -> io::Result<Self> {
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("no address in {:?}", address));
+        for addr in address.split(',').flat_map(|a| a.to_socket_addrs().into_iter().flatten()) {
+            match Self::open(addr, timeout) {
+                Ok(session) => return Ok(session),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    fn open(addr: std::net::SocketAddr, timeout: Duration) -> io::Result<Self> {
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Session::handshake(stream, timeout)
+    }
+}
+
+impl<S: Read + Write> Session<S> {
+    fn handshake(stream: S, timeout: Duration) -> io::Result<Self> {
+        let mut session = Self { stream };
+
+        let mut request = vec![];
+        put_i32(&mut request, 0); // protocol version
+        put_i64(&mut request, 0); // last zxid seen
+        put_i32(&mut request, timeout.as_millis() as i32);
+        put_i64(&mut request, 0); // session id
+        put_buffer(&mut request, Some(&[0; 16])); // password
+        request.push(0); // read only
+        session.send(&request)?;
+
+        let response = session.receive()?;
+        let mut reader = Reader(&response);
+        reader.i32()?; // protocol version
+        if reader.i32()? <= 0 {
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "session refused by server"));
+        }
+        Ok(session)
+    }
+
+    fn reconfig(&mut self, auth: Option<(&str, &[u8])>, joining: Option<&str>, leaving: Option<&str>, from_config: i64) -> Result<(Vec<u8>, i64), ZkError> {
+        if let Some((scheme, credentials)) = auth {
+            let mut body = vec![];
+            put_i32(&mut body, 0); // auth type
+            put_buffer(&mut body, Some(scheme.as_bytes()));
+            put_buffer(&mut body, Some(credentials));
+            self.call(AUTH_XID, OP_AUTH, &body)?;
+        }
+
+        let mut body = vec![];
+        put_buffer(&mut body, joining.map(str::as_bytes));
+        put_buffer(&mut body, leaving.map(str::as_bytes));
+        put_buffer(&mut body, None); // new members, for non-incremental reconfig
+        put_i64(&mut body, from_config);
+        let response = self.call(1, OP_RECONFIG, &body)?;
+
+        let mut reader = Reader(&response);
+        let data = reader.buffer().map_err(connection_loss)?;
+        reader.i64().map_err(connection_loss)?; // czxid
+        let mzxid = reader.i64().map_err(connection_loss)?;
+        Ok((data, mzxid))
+    }
+
+    /// Send a request and return the body of its reply, failing with the server's error.
+    fn call(&mut self, xid: i32, opcode: i32, body: &[u8]) -> Result<Vec<u8>, ZkError> {
+        let mut request = vec![];
+        put_i32(&mut request, xid);
+        put_i32(&mut request, opcode);
+        request.extend_from_slice(body);
+        self.send(&request).map_err(connection_loss)?;
+        loop {
+            let response = self.receive().map_err(connection_loss)?;
+            let mut reader = Reader(&response);
+            let reply_xid = reader.i32().map_err(connection_loss)?;
+            reader.i64().map_err(connection_loss)?; // zxid
+            let err = reader.i32().map_err(connection_loss)?;
+            if reply_xid == NOTIFICATION_XID || reply_xid == PING_XID {
+                continue;
+            }
+            if err != 0 {
+                return Err(ZkError::from(err));
+            }
+            return Ok(reader.0.to_vec());
+        }
+    }
+
+    fn send(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.stream.write_all(&(payload.len() as i32).to_be_bytes())?;
+        self.stream.write_all(payload)
+    }
+
+    fn receive(&mut self) -> io::Result<Vec<u8>> {
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let mut payload = vec![0; i32::from_be_bytes(len).max(0) as usize];
+        self.stream.read_exact(&mut payload)?;
+        Ok(payload)
+    }
+}
+
+fn put_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_i64(out: &mut Vec<u8>, value: i64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Length-prefixed bytes; strings are encoded the same way and None as length -1.
+fn put_buffer(out: &mut Vec<u8>, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            put_i32(out, value.len() as i32);
+            out.extend_from_slice(value);
+        }
+        None => put_i32(out, -1),
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated zookeeper response"));
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn buffer(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.i32()?;
+        Ok(self.take(len.max(0) as usize)?.to_vec())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A server end that answers from canned frames and records what was sent to it.
+    struct Wire {
+        replies: io::Cursor<Vec<u8>>,
+        sent: Vec<u8>,
+    }
+
+    impl Wire {
+        fn new(replies: &[Vec<u8>]) -> Self {
+            Self { replies: io::Cursor::new(replies.concat()), sent: vec![] }
+        }
+    }
+
+    impl Read for Wire {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        [&(payload.len() as i32).to_be_bytes()[..], payload].concat()
+    }
+
+    fn reply_header(xid: i32, zxid: i64, err: i32) -> Vec<u8> {
+        [&xid.to_be_bytes()[..], &zxid.to_be_bytes(), &err.to_be_bytes()].concat()
+    }
+
+    fn connect_reply(timeout: i32) -> Vec<u8> {
+        let mut reply = vec![];
+        put_i32(&mut reply, 0);
+        put_i32(&mut reply, timeout);
+        put_i64(&mut reply, 0x1234);
+        put_buffer(&mut reply, Some(&[0; 16]));
+        frame(&reply)
+    }
+
+    fn session(replies: &[Vec<u8>]) -> Session<Wire> {
+        let mut replies = replies.to_vec();
+        replies.insert(0, connect_reply(10_000));
+        let mut session = Session::handshake(Wire::new(&replies), Duration::from_secs(10)).unwrap();
+        session.stream.sent.clear();
+        session
+    }
+
+    #[test]
+    fn handshake_sends_a_fresh_connect_request() {
+        let session = Session::handshake(Wire::new(&[connect_reply(10_000)]), Duration::from_secs(10)).unwrap();
+        let mut expected = vec![];
+        put_i32(&mut expected, 0);
+        put_i64(&mut expected, 0);
+        put_i32(&mut expected, 10_000);
+        put_i64(&mut expected, 0);
+        put_buffer(&mut expected, Some(&[0; 16]));
+        expected.push(0);
+        assert_eq!(session.stream.sent, frame(&expected));
+
+        let refused = Session::handshake(Wire::new(&[connect_reply(0)]), Duration::from_secs(10));
+        assert_eq!(refused.err().unwrap().kind(), io::ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn reconfig_encodes_the_request_and_decodes_the_new_config() {
+        let mut body = reply_header(1, 0x200000005, 0);
+        put_buffer(&mut body, Some(b"server.1=zk-0:2888:3888:participant;2181\nversion=200000005"));
+        put_i64(&mut body, 0x100000000); // czxid
+        put_i64(&mut body, 0x200000005); // mzxid
+        body.extend_from_slice(&[0; 36]); // rest of the stat
+        let mut session = session(&[
+            frame(&reply_header(NOTIFICATION_XID, -1, 0)),
+            frame(&reply_header(PING_XID, -1, 0)),
+            frame(&body),
+        ]);
+
+        let (data, zxid) = session.reconfig(None, Some("server.2=zk-1:2888:3888:participant;2181"), None, 0x100000003).unwrap();
+        assert_eq!(data, b"server.1=zk-0:2888:3888:participant;2181\nversion=200000005");
+        assert_eq!(zxid, 0x200000005);
+
+        let mut expected = vec![];
+        put_i32(&mut expected, 1);
+        put_i32(&mut expected, OP_RECONFIG);
+        put_buffer(&mut expected, Some(b"server.2=zk-1:2888:3888:participant;2181"));
+        put_i32(&mut expected, -1);
+        put_i32(&mut expected, -1);
+        put_i64(&mut expected, 0x100000003);
+        assert_eq!(session.stream.sent, frame(&expected));
+    }
+
+    #[test]
+    fn reconfig_authenticates_first_and_surfaces_server_errors() {
+        let mut session = session(&[
+            frame(&reply_header(AUTH_XID, 0, 0)),
+            frame(&reply_header(1, 0, ZkError::BadVersion as i32)),
+        ]);
+
+        let result = session.reconfig(Some(("digest", b"admin:secret")), None, Some("3"), 7);
+        assert!(matches!(result, Err(ZkError::BadVersion)));
+
+        let mut auth = vec![];
+        put_i32(&mut auth, AUTH_XID);
+        put_i32(&mut auth, OP_AUTH);
+        put_i32(&mut auth, 0);
+        put_buffer(&mut auth, Some(b"digest"));
+        put_buffer(&mut auth, Some(b"admin:secret"));
+        assert!(session.stream.sent.starts_with(&frame(&auth)));
+    }
+
+    #[test]
+    fn truncated_replies_are_a_lost_connection() {
+        assert_eq!(Reader(&[0, 0]).i32().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(Reader(&[0, 0, 0, 10, 1, 2]).buffer().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(Reader(&[0xff, 0xff, 0xff, 0xff]).buffer().unwrap(), Vec::<u8>::new());
+
+        let mut session = session(&[frame(&reply_header(1, 0, 0)[..10])]);
+        assert!(matches!(session.reconfig(None, None, Some("3"), -1), Err(ZkError::ConnectionLoss)));
+    }
+}
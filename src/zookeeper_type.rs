@@ -1,28 +1,38 @@
 use serde::{Deserialize, Serialize};
 use k8s_openapi::api::core::v1 as v1;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1 as metav1;
-use k8s_openapi::api::core::v1::PersistentVolumeClaimSpec;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use std::collections::HashMap;
-use kube::api::{TypeMeta, ObjectMeta};
-use kube::{
-    api::{Api, DeleteParams, ListParams, Patch, PatchParams, PostParams, ResourceExt},
-    core::crd::CustomResourceExt,
-    Client, CustomResource,
-    runtime::controller::{Controller, Action}
-};
+use std::fmt;
+use std::time::Duration;
+use k8s_openapi::chrono::Utc;
+use kube::CustomResource;
 use schemars::JsonSchema;
 use super::status::ZookeeperClusterStatus;
 
 
-const DEFAULT_ZK_CONTAINER_REPOSITORY: &str = "default_zk_container_repository";
-const DEFAULT_ZK_CONTAINER_VERSION: &str = "default_zk_container_version";
-const DEFAULT_ZK_CONTAINER_POLICY: &str = "default_zk_container_policy";
+const DEFAULT_ZK_CONTAINER_REPOSITORY: &str = "pravega/zookeeper";
+const DEFAULT_ZK_CONTAINER_VERSION: &str = "0.2.15";
+const DEFAULT_ZK_CONTAINER_POLICY: &str = PULL_IF_NOT_PRESENT;
+const DEFAULT_UPGRADE_TIMEOUT_SECONDS: u64 = 600;
 
 const PULL_ALWAYS: &str = "Always";
 const PULL_NEVER: &str = "Never";
 const PULL_IF_NOT_PRESENT: &str = "IfNotPresent";
 
 
+pub const KUBERNETES_CLUSTER_DOMAIN: &str = "cluster.local";
+
+pub const SERVICE_TYPE_LOAD_BALANCER: &str = "LoadBalancer";
+pub const SERVICE_TYPE_NODE_PORT: &str = "NodePort";
+
+pub const RECLAIM_POLICY_RETAIN: &str = "Retain";
+pub const RECLAIM_POLICY_DELETE: &str = "Delete";
+
+pub const AUTH_SCHEME_DIGEST: &str = "digest";
+pub const AUTH_SECRET_USERNAME_KEY: &str = "username";
+pub const AUTH_SECRET_PASSWORD_KEY: &str = "password";
+
 pub const DEFAULT_TERMINATION_GRACE_PERIOD: i64 = 30;
 pub const DEFAULT_ZOOKEEPER_CACHE_VOLUME_SIZE: &str = "20Gi";
 pub const DEFAULT_READINESS_PROBE_INITIAL_DELAY_SECONDS: i32 = 10;
@@ -38,11 +48,11 @@ pub const DEFAULT_LIVENESS_PROBE_TIMEOUT_SECONDS: i32 = 10;
 
 // Implement the ContainerImage struct
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct ContainerImage {
-    repository: Option<String>,
-    tag: Option<String>,
+pub struct ContainerImage {
+    pub repository: Option<String>,
+    pub tag: Option<String>,
     #[serde(rename = "pullPolicy")]
-    pull_policy: Option<String>,
+    pub pull_policy: Option<String>,
 }
 impl ContainerImage {
     fn new() -> Self {
@@ -68,12 +78,13 @@ impl ContainerImage {
         }
         changed
     }
+}
 
-    fn to_string(&self) -> String {
-        if self.repository.is_none() && self.tag.is_none() {
-            return format!("{}:{}", self.repository.as_ref().unwrap(), self.tag.as_ref().unwrap());
-        }else {
-            return String::from("");
+impl fmt::Display for ContainerImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.repository.as_ref(), self.tag.as_ref()) {
+            (Some(repository), Some(tag)) => write!(f, "{}:{}", repository, tag),
+            _ => Ok(()),
         }
     }
 }
@@ -81,15 +92,15 @@ impl ContainerImage {
 
 // Implement the PodPolicy struct
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct PodPolicy {
+pub struct PodPolicy {
     #[serde(rename = "labels", skip_serializing_if = "Option::is_none")]
-    labels: Option<std::collections::BTreeMap<String, String>>,
+    pub labels: Option<std::collections::BTreeMap<String, String>>,
 
     #[serde(rename = "nodeSelector", skip_serializing_if = "Option::is_none")]
-    node_selector: Option<std::collections::BTreeMap<String, String>>,
+    pub node_selector: Option<std::collections::BTreeMap<String, String>>,
 
     #[serde(rename = "affinity", skip_serializing_if = "Option::is_none")]
-    affinity: Option<v1::Affinity>,
+    pub affinity: Option<v1::Affinity>,
 
     // #[serde(rename = "topologySpreadConstraints", skip_serializing_if = "Vec::is_empty")]
     // topology_spread_constraints: Vec<TopologySpreadConstraint>,
@@ -104,16 +115,16 @@ struct PodPolicy {
     // env: Vec<EnvVar>,
 
     #[serde(rename = "annotations", skip_serializing_if = "Option::is_none")]
-    annotations: Option<std::collections::BTreeMap<String, String>>,
+    pub annotations: Option<std::collections::BTreeMap<String, String>>,
 
     // #[serde(rename = "securityContext", skip_serializing_if = "Option::is_none")]
     // security_context: Option<PodSecurityContext>,
 
     #[serde(rename = "terminationGracePeriodSeconds", skip_serializing_if = "Option::is_none")]
-    termination_grace_period_seconds: Option<i64>,
+    pub termination_grace_period_seconds: Option<i64>,
 
     #[serde(rename = "serviceAccountName", skip_serializing_if = "Option::is_none")]
-    service_account_name: Option<String>,
+    pub service_account_name: Option<String>,
 
     // #[serde(rename = "imagePullSecrets", skip_serializing_if = "Vec::is_empty")]
     // image_pull_secrets: Vec<LocalObjectReference>,
@@ -132,6 +143,7 @@ impl PodPolicy {
     }
     fn with_defaults(&mut self, z: &ZookeeperCluster) -> bool {
         let mut changed = false;
+        let name = z.metadata.name.clone().unwrap_or_default();
 
         if self.labels.is_none() {
             self.labels = Some(std::collections::BTreeMap::new());
@@ -148,18 +160,13 @@ impl PodPolicy {
             changed = true;
         }
 
-        if z.spec.pod.as_ref().unwrap().labels.is_none() {
-            self.labels = Some(std::collections::BTreeMap::new());
-            changed = true;
-        }
-
         if !self.labels.as_ref().unwrap().contains_key("app") {
-            self.labels.as_mut().unwrap().insert("app".to_owned(), z.metadata.name.unwrap());
+            self.labels.as_mut().unwrap().insert("app".to_owned(), name.clone());
             changed = true;
         }
 
         if !self.labels.as_ref().unwrap().contains_key("release") {
-            self.labels.as_mut().unwrap().insert("release".to_owned(), z.metadata.name.unwrap());
+            self.labels.as_mut().unwrap().insert("release".to_owned(), name.clone());
             changed = true;
         }
 
@@ -176,7 +183,7 @@ impl PodPolicy {
                                         metav1::LabelSelectorRequirement {
                                             key: "app".to_owned(),
                                             operator: "In".to_owned(),
-                                            values: Some(vec![z.metadata.name.unwrap()]),
+                                            values: Some(vec![name]),
                                         },
                                     ]),
                                     ..Default::default()
@@ -199,15 +206,15 @@ impl PodPolicy {
 
 // Implement the persistent struct
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct Persistence {
+pub struct Persistence {
     #[serde(rename = "reclaimPolicy", skip_serializing_if = "Option::is_none")]
-    volume_reclaim_policy: Option<String>,
+    pub volume_reclaim_policy: Option<String>,
 
     #[serde(rename = "spec", skip_serializing_if = "Option::is_none")]
-    persistent_volume_claim_spec: Option<v1::PersistentVolumeClaimSpec>,
+    pub persistent_volume_claim_spec: Option<v1::PersistentVolumeClaimSpec>,
 
     #[serde(rename = "annotations", skip_serializing_if = "Option::is_none")]
-    annotations: Option<std::collections::BTreeMap<String, String>>,
+    pub annotations: Option<std::collections::BTreeMap<String, String>>,
 }
 
 impl Persistence {
@@ -221,7 +228,7 @@ impl Persistence {
     pub fn with_defaults(&mut self) -> bool {
         let mut changed = false;
         if self.volume_reclaim_policy.is_none() {
-            self.volume_reclaim_policy = Some(String::from("Retain"));
+            self.volume_reclaim_policy = Some(RECLAIM_POLICY_RETAIN.to_owned());
             changed = true;
         }
         if self.persistent_volume_claim_spec.is_none() {
@@ -230,19 +237,29 @@ impl Persistence {
                 ..Default::default()
             });
         }
-        let mut spec = self.persistent_volume_claim_spec.take().unwrap();
+        let spec = self.persistent_volume_claim_spec.as_mut().unwrap();
         spec.access_modes = Some(vec![String::from("ReadWriteOnce")]);
-        self.persistent_volume_claim_spec = Some(spec);
-        let storage = self.persistent_volume_claim_spec.as_ref().unwrap().resources.as_ref().unwrap().requests.as_ref().unwrap().get("storage");
-        // @TODO: What is storage == 0?
-        // if storage.is_none() || storage.unwrap().is_zero() {
-        //     self.persistent_volume_claim_spec.resources.requests =
-        //         std::collections::BTreeMap::from_iter(vec![(
-        //             v1::ResourceStorage,
-        //             resource::Quantity(DefaultZookeeperCacheVolumeSize.to_owned()),
-        //         )]);
-        //     changed = true;
-        // }
+        // An empty or zero storage request falls back to the default cache volume size.
+        let requests = spec
+            .resources
+            .get_or_insert_with(Default::default)
+            .requests
+            .get_or_insert_with(Default::default);
+        let storage_is_zero = match requests.get("storage") {
+            None => true,
+            Some(q) => q
+                .0
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == '.')
+                .all(|c| c == '0' || c == '.'),
+        };
+        if storage_is_zero {
+            requests.insert(
+                "storage".to_owned(),
+                Quantity(DEFAULT_ZOOKEEPER_CACHE_VOLUME_SIZE.to_owned()),
+            );
+            changed = true;
+        }
         changed
     }
 }
@@ -394,22 +411,27 @@ pub struct Probe {
 }
 
 impl Probe {
-    fn default() -> Self {
-        Probe {
-            initial_delay_seconds: None,
-            period_seconds: None,
-            failure_threshold: None,
-            success_threshold: None,
-            timeout_seconds: None,
+    /// Build a k8s probe that runs `command` inside the zookeeper container.
+    pub fn generate_probe(&self, command: &str) -> v1::Probe {
+        v1::Probe {
+            exec: Some(v1::ExecAction {
+                command: Some(vec![command.to_owned()]),
+            }),
+            initial_delay_seconds: self.initial_delay_seconds,
+            period_seconds: self.period_seconds,
+            failure_threshold: self.failure_threshold,
+            success_threshold: self.success_threshold,
+            timeout_seconds: self.timeout_seconds,
+            ..Default::default()
         }
     }
 }
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct Probes {
+pub struct Probes {
     #[serde(rename="readinessProbe", skip_serializing_if = "Option::is_none")]
-    readiness_probe: Option<Probe>,
+    pub readiness_probe: Option<Probe>,
     #[serde(rename="livenessProbe", skip_serializing_if = "Option::is_none")]
-    liveness_probe: Option<Probe>,
+    pub liveness_probe: Option<Probe>,
 }
 
 impl Probes {
@@ -448,9 +470,9 @@ impl Probes {
 
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
-struct Ephemeral{
+pub struct Ephemeral{
     #[serde(rename="emptydirvolumesource", skip_serializing_if = "Option::is_none")]
-    emptydirvolumesource: Option<v1::EmptyDirVolumeSource>,
+    pub emptydirvolumesource: Option<v1::EmptyDirVolumeSource>,
 }
 impl Ephemeral {
     fn new() -> Self {
@@ -461,6 +483,59 @@ impl Ephemeral {
 }
 
 
+// Implement the external client service struct
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ExternalClientService {
+    /// Service type used to expose the client port, either LoadBalancer or NodePort.
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub service_type: Option<String>,
+
+    #[serde(rename = "annotations", skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<std::collections::BTreeMap<String, String>>,
+
+    #[serde(rename = "loadBalancerSourceRanges", skip_serializing_if = "Option::is_none")]
+    pub load_balancer_source_ranges: Option<Vec<String>>,
+
+    /// Fixed node port for the NodePort type; allocated by Kubernetes when unset.
+    #[serde(rename = "nodePort", skip_serializing_if = "Option::is_none")]
+    pub node_port: Option<i32>,
+}
+
+impl ExternalClientService {
+    fn with_defaults(&mut self) -> bool {
+        let mut changed = false;
+        if self.service_type.is_none() {
+            self.service_type = Some(SERVICE_TYPE_LOAD_BALANCER.to_owned());
+            changed = true;
+        }
+        changed
+    }
+}
+
+
+// Implement the auth struct
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ZookeeperAuth {
+    /// Authentication scheme the operator uses against the ensemble. Only digest is
+    /// supported; SASL needs a JAAS login the client library does not implement.
+    #[serde(rename = "scheme", skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<String>,
+
+    /// Secret in the cluster's namespace holding the `username` and `password` keys.
+    #[serde(rename = "secretName")]
+    pub secret_name: String,
+}
+
+impl ZookeeperAuth {
+    fn with_defaults(&mut self) -> bool {
+        let mut changed = false;
+        if self.scheme.is_none() {
+            self.scheme = Some(AUTH_SCHEME_DIGEST.to_owned());
+            changed = true;
+        }
+        changed
+    }
+}
 
 
 // Implement the ZookeeperClusterSpec struct
@@ -472,47 +547,63 @@ impl Ephemeral {
     plural = "zookeeperclusters",
     shortname = "zk",
     status = "ZookeeperClusterStatus",
-    namespaced
-    // printcolumn = r#"[
-    //     {"name": "Replicas", "type": "integer", "jsonPath": ".spec.replicas", "description": "The number of ZooKeeper servers in the ensemble"},
-    //     {"name": "Ready Replicas", "type": "integer", "jsonPath": ".status.readyReplicas", "description": "The number of ZooKeeper servers in the ensemble that are in a Ready state"},
-    //     {"name": "Version", "type": "string", "jsonPath": ".status.currentVersion", "description": "The current Zookeeper version"},
-    //     {"name": "Desired Version", "type": "string", "jsonPath": ".spec.image.tag", "description": "The desired Zookeeper version"},
-    //     {"name": "Internal Endpoint", "type": "string", "jsonPath": ".status.internalClientEndpoint", "description": "Client endpoint internal to cluster network"},
-    //     {"name": "External Endpoint", "type": "string", "jsonPath": ".status.externalClientEndpoint", "description": "Client endpoint external to cluster network via LoadBalancer"},
-    //     {"name": "Age", "type": "date", "jsonPath": ".metadata.creationTimestamp"}
-    // ]"#
+    namespaced,
+    printcolumn = r#"{"name": "Replicas", "type": "integer", "jsonPath": ".spec.replicas", "description": "The number of ZooKeeper servers in the ensemble"}"#,
+    printcolumn = r#"{"name": "Ready Replicas", "type": "integer", "jsonPath": ".status.readyReplicas", "description": "The number of ZooKeeper servers in the ensemble that are in a Ready state"}"#,
+    printcolumn = r#"{"name": "Version", "type": "string", "jsonPath": ".status.currentVersion", "description": "The current Zookeeper version"}"#,
+    printcolumn = r#"{"name": "Desired Version", "type": "string", "jsonPath": ".spec.image.tag", "description": "The desired Zookeeper version"}"#,
+    printcolumn = r#"{"name": "Internal Endpoint", "type": "string", "jsonPath": ".status.internalClientEndpoint", "description": "Client endpoint internal to cluster network"}"#,
+    printcolumn = r#"{"name": "External Endpoint", "type": "string", "jsonPath": ".status.externalClientEndpoint", "description": "Client endpoint external to cluster network via LoadBalancer"}"#,
+    printcolumn = r#"{"name": "Age", "type": "date", "jsonPath": ".metadata.creationTimestamp"}"#
 )]
 pub struct ZookeeperClusterSpec{
     #[serde(rename = "image", skip_serializing_if = "Option::is_none")]
-    image: Option<ContainerImage>,
+    pub image: Option<ContainerImage>,
 
     #[serde(rename = "replicas", default)]
     pub replicas: i32,
 
     #[serde(rename = "storageType", skip_serializing_if = "Option::is_none")]
-    storagetype: Option<String>,
+    pub storagetype: Option<String>,
 
     #[serde(rename = "persistence", skip_serializing_if = "Option::is_none")]
-    persistence: Option<Persistence>,
+    pub persistence: Option<Persistence>,
 
     #[serde(rename = "triggerRollingRestart", skip_serializing_if = "Option::is_none")]
-    triggerRollingRestart: Option<bool>,
+    pub trigger_rolling_restart: Option<bool>,
 
     #[serde(rename = "config", skip_serializing_if = "Option::is_none")]
-    zkconfig: Option<ZookeeperConfig>,
+    pub zkconfig: Option<ZookeeperConfig>,
 
     #[serde(rename = "probes", skip_serializing_if = "Option::is_none")]
-    probes: Option<Probes>,
+    pub probes: Option<Probes>,
 
     #[serde(rename = "ports", skip_serializing_if = "Option::is_none")]
-    ports: Option<Vec<v1::ContainerPort>>,
+    pub ports: Option<Vec<v1::ContainerPort>>,
 
     #[serde(rename = "pod", skip_serializing_if = "Option::is_none")]
-    pod: Option<PodPolicy>,
+    pub pod: Option<PodPolicy>,
 
     #[serde(rename = "ephemeral", skip_serializing_if = "Option::is_none")]
-    ephemeral: Option<Ephemeral>,
+    pub ephemeral: Option<Ephemeral>,
+
+    #[serde(rename = "externalClientService", skip_serializing_if = "Option::is_none")]
+    pub external_client_service: Option<ExternalClientService>,
+
+    /// Overrides the PodDisruptionBudget maxUnavailable computed from replicas.
+    #[serde(rename = "maxUnavailableReplicas", skip_serializing_if = "Option::is_none")]
+    pub max_unavailable_replicas: Option<i32>,
+
+    /// Credentials the operator authenticates with. When set, ACLs are enforced and the
+    /// znodes the operator creates are writable by it alone, though still readable by anyone.
+    /// The servers take the same credentials as their superuser, which reconfig requires.
+    #[serde(rename = "auth", skip_serializing_if = "Option::is_none")]
+    pub auth: Option<ZookeeperAuth>,
+
+    /// Seconds a version upgrade may go without another member updating before the
+    /// operator gives up and rolls the ensemble back to status.currentVersion.
+    #[serde(rename = "upgradeTimeoutSeconds", skip_serializing_if = "Option::is_none")]
+    pub upgrade_timeout_seconds: Option<u64>,
 }
 
 
@@ -524,18 +615,23 @@ pub struct ZookeeperClusterSpec{
 
 
 impl ZookeeperClusterSpec {
+    #[allow(dead_code)]
     pub fn new() -> ZookeeperClusterSpec {
         ZookeeperClusterSpec {
             image: None,
             replicas: 3,
             storagetype: None,
             persistence: None,
-            triggerRollingRestart: None,
+            trigger_rolling_restart: None,
             zkconfig: None,
             probes: None,
             ports: None,
             pod: None,
             ephemeral: None,
+            external_client_service: None,
+            max_unavailable_replicas: None,
+            auth: None,
+            upgrade_timeout_seconds: None,
         }
     }
     pub fn with_defaults(&mut self, z: & ZookeeperCluster) -> bool{
@@ -694,25 +790,179 @@ impl ZookeeperClusterSpec {
                 changed = true;
             }
         }
+
+        if let Some(external) = self.external_client_service.as_mut() {
+            if external.with_defaults() {
+                changed = true;
+            }
+        }
+
+        if let Some(auth) = self.auth.as_mut() {
+            if auth.with_defaults() {
+                changed = true;
+            }
+        }
         changed
     }
+
+    /// Reject specs the operator cannot turn into a working ensemble.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.replicas < 0 {
+            return Err(format!("replicas must not be negative, got {}", self.replicas));
+        }
+        if let Some(policy) = self.image.as_ref().and_then(|i| i.pull_policy.as_deref()) {
+            if ![PULL_ALWAYS, PULL_NEVER, PULL_IF_NOT_PRESENT].contains(&policy) {
+                return Err(format!("unknown image pullPolicy {:?}", policy));
+            }
+        }
+        if let Some(storage_type) = self.storagetype.as_deref() {
+            if !["", "persistence", "ephemeral"].contains(&storage_type) {
+                return Err(format!("unknown storageType {:?}, expected persistence or ephemeral", storage_type));
+            }
+        }
+        if let Some(service_type) = self.external_client_service.as_ref().and_then(|e| e.service_type.as_deref()) {
+            if ![SERVICE_TYPE_LOAD_BALANCER, SERVICE_TYPE_NODE_PORT].contains(&service_type) {
+                return Err(format!("unknown externalClientService type {:?}, expected LoadBalancer or NodePort", service_type));
+            }
+        }
+        if let Some(max_unavailable) = self.max_unavailable_replicas {
+            if max_unavailable < 0 {
+                return Err(format!("maxUnavailableReplicas must not be negative, got {}", max_unavailable));
+            }
+        }
+        if let Some(policy) = self.persistence.as_ref().and_then(|p| p.volume_reclaim_policy.as_deref()) {
+            if ![RECLAIM_POLICY_RETAIN, RECLAIM_POLICY_DELETE].contains(&policy) {
+                return Err(format!("unknown persistence reclaimPolicy {:?}, expected Retain or Delete", policy));
+            }
+        }
+        if let Some(auth) = &self.auth {
+            if let Some(scheme) = auth.scheme.as_deref().filter(|&s| s != AUTH_SCHEME_DIGEST) {
+                return Err(format!("unsupported auth scheme {:?}, only digest is supported", scheme));
+            }
+            if auth.secret_name.is_empty() {
+                return Err("auth.secretName must not be empty".to_owned());
+            }
+        }
+        if self.upgrade_timeout_seconds == Some(0) {
+            return Err("upgradeTimeoutSeconds must be positive".to_owned());
+        }
+        Ok(())
+    }
 }
 
 
 impl ZookeeperCluster {
     pub fn with_defaults(&mut self)->bool{
         let mut changed = false;
-        let spec = &mut self.spec;
-        let temp = self;
-        if spec.with_defaults(temp) {
+        // spec defaults read the cluster metadata, so hand them a snapshot of it
+        let temp = self.clone();
+        if self.spec.with_defaults(&temp) {
             changed = true;
         }
         changed
     }
     pub fn get_trigger_rolling_restart(&self) -> bool {
-        if self.spec.triggerRollingRestart.is_none() {
+        if self.spec.trigger_rolling_restart.is_none() {
             return false;
         }
-        self.spec.triggerRollingRestart.unwrap()
+        self.spec.trigger_rolling_restart.unwrap()
+    }
+    /// Number of servers that may be voluntarily disrupted while a majority of the
+    /// ensemble stays up, unless `spec.maxUnavailableReplicas` overrides it.
+    pub fn get_max_unavailable_replicas(&self) -> i32 {
+        match self.spec.max_unavailable_replicas {
+            Some(max_unavailable) => max_unavailable,
+            None => ((self.spec.replicas - 1) / 2).max(0),
+        }
     }
-}
\ No newline at end of file
+    /// How long an upgrade may stall before it is rolled back, ten minutes unless
+    /// `spec.upgradeTimeoutSeconds` says otherwise.
+    pub fn get_upgrade_timeout(&self) -> Duration {
+        Duration::from_secs(self.spec.upgrade_timeout_seconds.unwrap_or(DEFAULT_UPGRADE_TIMEOUT_SECONDS))
+    }
+    pub fn get_headless_service_name(&self) -> String {
+        format!("{}-headless", self.metadata.name.clone().unwrap_or_default())
+    }
+    pub fn get_client_service_name(&self) -> String {
+        format!("{}-client", self.metadata.name.clone().unwrap_or_default())
+    }
+    pub fn get_admin_server_service_name(&self) -> String {
+        format!("{}-admin-server", self.metadata.name.clone().unwrap_or_default())
+    }
+    pub fn get_external_client_service_name(&self) -> String {
+        format!("{}-client-external", self.metadata.name.clone().unwrap_or_default())
+    }
+    /// host:port of the client Service, reachable from anywhere in the cluster.
+    pub fn get_client_service_address(&self) -> String {
+        format!(
+            "{}.{}.svc.{}:{}",
+            self.get_client_service_name(),
+            self.metadata.namespace.clone().unwrap_or_default(),
+            KUBERNETES_CLUSTER_DOMAIN,
+            self.get_port("client", 2181),
+        )
+    }
+    /// Whether data volume claims go away with the members using them.
+    pub fn reclaims_volumes(&self) -> bool {
+        self.spec.storagetype.as_deref() != Some("ephemeral")
+            && self.spec.persistence.as_ref().and_then(|p| p.volume_reclaim_policy.as_deref()) == Some(RECLAIM_POLICY_DELETE)
+    }
+    /// Name of the StatefulSet pod with the given ordinal.
+    pub fn get_member_name(&self, ordinal: i32) -> String {
+        format!("{}-{}", self.metadata.name.clone().unwrap_or_default(), ordinal)
+    }
+    /// Ordinal of a member pod from its name, or None for any other pod.
+    pub fn get_member_ordinal(&self, pod_name: &str) -> Option<i32> {
+        pod_name
+            .strip_prefix(self.metadata.name.as_deref().unwrap_or_default())?
+            .strip_prefix('-')?
+            .parse()
+            .ok()
+    }
+    /// Stable DNS name of a member pod through the headless Service.
+    pub fn get_member_address(&self, ordinal: i32) -> String {
+        format!(
+            "{}.{}.{}.svc.{}",
+            self.get_member_name(ordinal),
+            self.get_headless_service_name(),
+            self.metadata.namespace.clone().unwrap_or_default(),
+            KUBERNETES_CLUSTER_DOMAIN,
+        )
+    }
+    pub fn get_config_map_name(&self) -> String {
+        format!("{}-configmap", self.metadata.name.clone().unwrap_or_default())
+    }
+    /// Container port with the given name, falling back to `default` when it is not declared.
+    pub fn get_port(&self, name: &str, default: i32) -> i32 {
+        self.spec
+            .ports
+            .as_ref()
+            .and_then(|ports| ports.iter().find(|p| p.name.as_deref() == Some(name)))
+            .map(|p| p.container_port)
+            .unwrap_or(default)
+    }
+}
+
+/// Pod template annotation stamped to make the StatefulSet roll every server.
+pub const ROLLING_RESTART_ANNOTATION: &str = "restartTime";
+
+pub fn get_rolling_restart_annotation() -> (String, String) {
+    (ROLLING_RESTART_ANNOTATION.to_owned(), Utc::now().to_rfc3339())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_unavailable_keeps_a_quorum() {
+        let mut z = ZookeeperCluster::new("zk", ZookeeperClusterSpec::new());
+        for (replicas, max_unavailable) in [(1, 0), (3, 1), (4, 1), (5, 2)] {
+            z.spec.replicas = replicas;
+            assert_eq!(z.get_max_unavailable_replicas(), max_unavailable, "replicas {}", replicas);
+        }
+        z.spec.max_unavailable_replicas = Some(0);
+        assert_eq!(z.get_max_unavailable_replicas(), 0);
+    }
+}
@@ -0,0 +1,111 @@
+/// A zookeeper release line, major and minor version.
+type Line = (u64, u64);
+
+/// Release lines of zookeeper and the lines each one can be rolled to directly. Anything
+/// else, like 3.4 to 3.6, skips a release whose snapshot and protocol changes the later
+/// one relies on, and has to be done a line at a time.
+const UPGRADE_PATHS: &[(Line, &[Line])] = &[
+    ((3, 4), &[(3, 5)]),
+    ((3, 5), &[(3, 6)]),
+    ((3, 6), &[(3, 7), (3, 8)]),
+    ((3, 7), &[(3, 8)]),
+    ((3, 8), &[(3, 9)]),
+];
+
+
+/// Version parsed from an image tag such as `3.8.4`, `v3.6.3` or `0.2.15-alpine`. Tags of
+/// the pravega/zookeeper image carry a 0.x image version rather than a zookeeper one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    pub fn parse(tag: &str) -> Option<Self> {
+        let version = tag.strip_prefix('v').unwrap_or(tag);
+        let version = version.split(['-', '+']).next()?;
+        let mut parts = version.split('.').map(|p| p.parse::<u64>());
+        let major = parts.next()?.ok()?;
+        let minor = parts.next()?.ok()?;
+        let patch = match parts.next() {
+            Some(patch) => patch.ok()?,
+            None => 0,
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        Some(Self { major, minor, patch })
+    }
+
+    fn line(&self) -> Line {
+        (self.major, self.minor)
+    }
+}
+
+/// Check that an ensemble running image tag `from` can be rolled to tag `to`, explaining
+/// why not otherwise. Patch releases move freely within a line; image versions only
+/// move forward; zookeeper versions follow UPGRADE_PATHS.
+pub fn check_upgrade(from: &str, to: &str) -> Result<(), String> {
+    let parse = |tag: &str| Version::parse(tag).ok_or_else(|| format!("cannot tell the version of image tag {:?}", tag));
+    let (from_version, to_version) = (parse(from)?, parse(to)?);
+    if from_version.line() == to_version.line() {
+        return Ok(());
+    }
+    if (from_version.major == 0) != (to_version.major == 0) {
+        return Err(format!("cannot upgrade between image version {} and zookeeper version {}", from, to));
+    }
+    if to_version < from_version {
+        return Err(format!("downgrading from {} to {} is not supported", from, to));
+    }
+    if from_version.major == 0 {
+        return Ok(());
+    }
+    let allowed = UPGRADE_PATHS
+        .iter()
+        .find(|(line, _)| *line == from_version.line())
+        .map(|(_, targets)| *targets)
+        .unwrap_or_default();
+    if allowed.contains(&to_version.line()) {
+        return Ok(());
+    }
+    match allowed.iter().max() {
+        Some((major, minor)) => Err(format!(
+            "upgrading from {} to {} is not supported, upgrade to {}.{} first", from, to, major, minor
+        )),
+        None => Err(format!("upgrading from {} to {} is not supported", from, to)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_common_tag_shapes() {
+        assert_eq!(Version::parse("3.8.4"), Some(Version { major: 3, minor: 8, patch: 4 }));
+        assert_eq!(Version::parse("v3.6"), Some(Version { major: 3, minor: 6, patch: 0 }));
+        assert_eq!(Version::parse("0.2.15-alpine"), Some(Version { major: 0, minor: 2, patch: 15 }));
+        assert_eq!(Version::parse("latest"), None);
+        assert_eq!(Version::parse("3.8.4.1"), None);
+    }
+
+    #[test]
+    fn follows_the_upgrade_paths() {
+        assert!(check_upgrade("3.6.3", "3.6.4").is_ok());
+        assert!(check_upgrade("3.6.4", "3.6.3").is_ok());
+        assert!(check_upgrade("3.5.9", "3.6.3").is_ok());
+        assert!(check_upgrade("0.2.14", "0.2.15").is_ok());
+        assert_eq!(
+            check_upgrade("3.4.14", "3.6.3"),
+            Err("upgrading from 3.4.14 to 3.6.3 is not supported, upgrade to 3.5 first".to_owned())
+        );
+        assert!(check_upgrade("3.7.1", "3.6.3").is_err());
+        assert!(check_upgrade("0.2.15", "0.2.9").is_ok());
+        assert!(check_upgrade("0.3.0", "0.2.15").is_err());
+        assert!(check_upgrade("0.2.15", "3.8.4").is_err());
+        assert!(check_upgrade("latest", "3.8.4").is_err());
+    }
+}